@@ -0,0 +1,79 @@
+//! Runs a long chain of nodes, with chains fused into a single task and with
+//! every node in its own task.  A fused chain hands every result straight to
+//! the next node, without a channel, so it should get close to the sequential
+//! reference evaluation, which runs the same operations without any task or
+//! channel, while every unfused node pays for its own task and channel.
+//!
+//! Run with `cargo bench --bench chain`.
+
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Runtime;
+
+use computation_dag_async::computation::Computation;
+use computation_dag_async::dag::Dag;
+use computation_dag_async::operation::{Operation, OperationType};
+
+fn chain(length: usize) -> Dag {
+    let mut dag: Dag = Default::default();
+    let operation = Operation::new(OperationType::Sum);
+    let mut id = dag.add_node(operation.clone(), vec![]);
+    for _ in 1..length {
+        id = dag.add_node(operation.clone(), vec![id]);
+    }
+    dag
+}
+
+/// Best time out of a few runs
+fn best<F: FnMut() -> Duration>(mut run: F) -> Duration {
+    (0..5).map(|_| run()).min().unwrap()
+}
+
+/// Best time of the computation, including its creation
+fn measure(runtime: &mut Runtime, dag: &Dag, fusion: bool) -> Duration {
+    best(|| {
+        let start = Instant::now();
+        let mut computation = Computation::new(dag);
+        if !fusion {
+            computation.disable_fusion();
+        }
+        let report = runtime.block_on(computation.process(1u64));
+        assert!(report.is_success());
+        start.elapsed()
+    })
+}
+
+/// Best time of the sequential reference evaluation
+fn measure_sequential(dag: &Dag) -> Duration {
+    best(|| {
+        let start = Instant::now();
+        assert_eq!(dag.evaluate_sequential(1u64).len(), dag.nodes.len());
+        start.elapsed()
+    })
+}
+
+/// Time per node, in nanoseconds
+fn per_node(duration: Duration, length: usize) -> String {
+    format!("{:.0}ns", duration.as_nanos() as f64 / length as f64)
+}
+
+fn main() {
+    let mut runtime = Runtime::new().unwrap();
+    println!("{:<10} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "nodes", "sequential", "fused", "unfused", "seq/node", "fused/node", "unfused/node");
+    for length in [1_000, 10_000, 100_000] {
+        let dag = chain(length);
+        let sequential = measure_sequential(&dag);
+        let fused = measure(&mut runtime, &dag, true);
+        let unfused = measure(&mut runtime, &dag, false);
+        println!("{:<10} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+            length,
+            format!("{:.2?}", sequential),
+            format!("{:.2?}", fused),
+            format!("{:.2?}", unfused),
+            per_node(sequential, length),
+            per_node(fused, length),
+            per_node(unfused, length),
+        );
+    }
+}
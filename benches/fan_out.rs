@@ -0,0 +1,133 @@
+//! Runs a node whose large value is consumed by many children, measuring the
+//! time taken along with the memory allocated while running.  Every case also
+//! runs with a hook copying the inputs of every node, as a baseline of what
+//! sending every child its own copy of the value costs.
+//!
+//! Run with `cargo bench --bench fan_out`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::iter::{Product, Sum};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::runtime::Runtime;
+
+use computation_dag_async::computation::Computation;
+use computation_dag_async::dag::{Dag, NodeId};
+use computation_dag_async::hook::NodeHook;
+use computation_dag_async::operation::{Operation, OperationType};
+
+/// Counts the bytes allocated, and the most bytes allocated at once
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        let live = LIVE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(live, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Large value, combined element by element
+#[derive(Clone, Debug, Default)]
+struct Tensor(Vec<f64>);
+
+impl<'a> Sum<&'a Tensor> for Tensor {
+    fn sum<I: Iterator<Item = &'a Tensor>>(iter: I) -> Self {
+        iter.fold(Tensor::default(), |total, tensor| match total.0.is_empty() {
+            true => tensor.clone(),
+            false => Tensor(total.0.iter().zip(&tensor.0).map(|(a, b)| a + b).collect()),
+        })
+    }
+}
+
+impl<'a> Product<&'a Tensor> for Tensor {
+    fn product<I: Iterator<Item = &'a Tensor>>(iter: I) -> Self {
+        iter.fold(Tensor::default(), |total, tensor| match total.0.is_empty() {
+            true => tensor.clone(),
+            false => Tensor(total.0.iter().zip(&tensor.0).map(|(a, b)| a * b).collect()),
+        })
+    }
+}
+
+/// Gives every node its own copy of each input instead of the shared value
+struct Copying;
+
+impl NodeHook<Tensor> for Copying {
+    fn before(&self, _id: NodeId, _operation: &Operation, inputs: &mut Vec<Arc<Tensor>>) -> Result<(), String> {
+        inputs.iter_mut().for_each(|input| *input = Arc::new(Tensor::clone(input)));
+        Ok(())
+    }
+}
+
+/// One node summing the initial value, read by `width` children
+fn fan_out(width: usize, children: OperationType) -> Dag {
+    let mut dag: Dag = Default::default();
+    let id = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+    (0..width).for_each(|_| { dag.add_node(Operation::new(children), vec![id]); });
+    dag
+}
+
+struct Measure {
+    time: Duration,
+    allocated: usize,
+    peak: usize,
+}
+
+fn measure(runtime: &mut Runtime, dag: &Dag, initial: &Tensor, copying: bool) -> Measure {
+    let mut computation = Computation::new(dag);
+    if copying {
+        computation.add_hook(Copying);
+    }
+    let initial = initial.clone();
+    let base = LIVE.load(Ordering::Relaxed);
+    ALLOCATED.store(0, Ordering::Relaxed);
+    PEAK.store(base, Ordering::Relaxed);
+    let start = Instant::now();
+    let report = runtime.block_on(computation.process(initial));
+    let time = start.elapsed();
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    let peak = PEAK.load(Ordering::Relaxed) - base;
+    assert!(report.is_success());
+    Measure { time, allocated, peak }
+}
+
+fn main() {
+    const RUNS: usize = 10;
+    const MIB: f64 = (1 << 20) as f64;
+    let mut runtime = Runtime::new().unwrap();
+    // 8 MiB of values
+    let initial = Tensor(vec![1.0; 1 << 20]);
+    println!("{:<24} {:>8} {:>12} {:>16} {:>12}", "benchmark", "inputs", "time", "allocated", "peak");
+    for children in [OperationType::Default, OperationType::Sum] {
+        for width in [16, 64, 256] {
+            let dag = fan_out(width, children);
+            for copying in [true, false] {
+                let measures: Vec<Measure> = (0..RUNS).map(|_| measure(&mut runtime, &dag, &initial, copying)).collect();
+                let best = measures.iter().min_by_key(|measure| measure.time).unwrap();
+                println!(
+                    "{:<24} {:>8} {:>12} {:>12.1} MiB {:>8.1} MiB",
+                    format!("{:?} x {}", children, width),
+                    if copying { "copied" } else { "shared" },
+                    format!("{:.2?}", best.time),
+                    best.allocated as f64 / MIB,
+                    best.peak as f64 / MIB,
+                );
+            }
+        }
+    }
+}
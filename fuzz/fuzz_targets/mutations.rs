@@ -0,0 +1,118 @@
+#![no_main]
+
+use std::collections::BTreeMap;
+use std::num::Wrapping;
+use std::time::Duration;
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use tokio::runtime::Builder;
+
+use computation_dag_async::computation::Computation;
+use computation_dag_async::dag::{Dag, DagError, NodeId};
+use computation_dag_async::operation::{Operation, OperationType};
+
+/// Longest sequence of mutations applied to a single DAG
+const MAX_MUTATIONS: usize = 64;
+
+/// Any run longer than this is considered hung, since no operation waits
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Nodes are referred to by their index among the existing nodes, sorted by
+/// id, so that most mutations apply to an actual node.  The parents of new
+/// nodes are raw ids instead, which are small enough to often exist, so that
+/// unknown parents are rejected too.
+#[derive(Arbitrary, Debug)]
+enum Mutation {
+    AddNode { operation: u8, parents: Vec<u8> },
+    AddEdge { parent: u8, child: u8 },
+    RemoveNode { node: u8 },
+    SetOperation { node: u8, operation: u8 },
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    initial: u64,
+    mutations: Vec<Mutation>,
+    /// Nodes to compute instead of the sink nodes, when not empty
+    targets: Vec<u8>,
+}
+
+/// Every operation but `Delay`, so that a slow run can only be a hang
+fn operation(byte: u8) -> Operation {
+    let operation_type = match byte % 3 {
+        0 => OperationType::Default,
+        1 => OperationType::Sum,
+        _ => OperationType::Product,
+    };
+    Operation::new(operation_type)
+}
+
+fn node(dag: &Dag, index: u8) -> Option<NodeId> {
+    let mut ids: Vec<NodeId> = dag.nodes.keys().copied().collect();
+    if ids.is_empty() {
+        return None;
+    }
+    ids.sort_unstable();
+    Some(ids[index as usize % ids.len()])
+}
+
+fn apply(dag: &mut Dag, mutation: Mutation) {
+    match mutation {
+        Mutation::AddNode { operation: byte, parents } => {
+            let parents: Vec<NodeId> = parents.into_iter().map(NodeId::from).collect();
+            let nodes = dag.nodes.len();
+            match dag.try_add_node(operation(byte), parents.clone()) {
+                Ok(id) => assert!(parents.iter().all(|parent_id| dag.nodes[parent_id].children.contains(&id))),
+                Err(DagError::UnknownNode(id)) => {
+                    assert!(parents.contains(&id) && !dag.nodes.contains_key(&id), "Existing parent rejected");
+                    assert_eq!(dag.nodes.len(), nodes, "Rejected node added anyway");
+                },
+                Err(error) => panic!("Unexpected error adding a node: {}", error),
+            }
+        },
+        Mutation::AddEdge { parent, child } => {
+            if let (Some(parent), Some(child)) = (node(dag, parent), node(dag, child)) {
+                // Edges creating a cycle are expected to be rejected
+                let _ = dag.add_edge(parent, child);
+            }
+        },
+        Mutation::RemoveNode { node: index } => {
+            if let Some(id) = node(dag, index) {
+                dag.remove_node(id).expect("Existing node not removed");
+            }
+        },
+        Mutation::SetOperation { node: index, operation: byte } => {
+            if let Some(id) = node(dag, index) {
+                dag.nodes.get_mut(&id).unwrap().operation = operation(byte);
+            }
+        },
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let mut dag: Dag = Default::default();
+    input.mutations.into_iter().take(MAX_MUTATIONS).for_each(|mutation| apply(&mut dag, mutation));
+    dag.validate().expect("Mutations produced an invalid DAG");
+
+    let initial = Wrapping(input.initial);
+    let expected = dag.evaluate_sequential(initial);
+    let targets: Vec<NodeId> = input.targets.into_iter().filter_map(|index| node(&dag, index)).collect();
+    let mut computation = Computation::new(&dag);
+    let outputs = if targets.is_empty() {
+        dag.nodes.iter()
+            .filter(|(_, node)| node.children.is_empty())
+            .map(|(id, _)| *id)
+            .collect()
+    } else {
+        computation.set_targets(targets.clone()).expect("Targets are nodes of the DAG");
+        targets
+    };
+
+    let mut runtime = Builder::new().threaded_scheduler().core_threads(2).enable_all().build().unwrap();
+    let report = runtime.block_on(async { tokio::time::timeout(TIMEOUT, computation.process(initial)).await })
+        .expect("Computation hung");
+    assert!(report.is_success(), "Computation failed: {:?}", report.errors());
+    let expected: BTreeMap<NodeId, Wrapping<u64>> = outputs.iter().map(|id| (*id, expected[id])).collect();
+    assert_eq!(report.results, expected);
+});
@@ -0,0 +1,343 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "serialize")]
+use std::{fs, path::PathBuf};
+#[cfg(feature = "serialize")]
+use serde::{Serialize, de::DeserializeOwned};
+
+#[cfg(feature = "serialize")]
+use crate::hash::StableHasher;
+use crate::operation::OperationType;
+
+/// Identifies the result of an operation applied to some inputs.  Different
+/// keys may share a hash, so caches compare the operation type and inputs
+/// before returning a value.
+pub struct CacheKey<'a, T> {
+    /// Hash of the operation type and inputs, from `cache_key`, only
+    /// meaningful within the process that computed it
+    pub hash: u64,
+    pub operation_type: OperationType,
+    pub inputs: &'a [Arc<T>],
+}
+
+/// Storage for node results, shared between computations
+pub trait ResultCache<T>: Send + Sync {
+    fn get(&self, key: &CacheKey<'_, T>) -> Option<T>;
+    fn insert(&self, key: &CacheKey<'_, T>, value: T);
+}
+
+/// Hashes the operation type along with the inputs, so that two nodes running
+/// the same operation on the same inputs share a hash.  Shared inputs, such as
+/// `Arc<T>`, hash like the values they point to.
+pub fn cache_key<T: Hash>(operation_type: OperationType, inputs: &[T]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    operation_type.hash(&mut hasher);
+    inputs.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Number of node results found in, or missing from, the cache during a run
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Cache used by a single run, counting hits and misses
+pub struct RunCache<T> {
+    backend: Arc<dyn ResultCache<T>>,
+    hash: fn(OperationType, &[Arc<T>]) -> u64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<T> RunCache<T> {
+    pub fn new(backend: Arc<dyn ResultCache<T>>, hash: fn(OperationType, &[Arc<T>]) -> u64) -> Self {
+        let hits = AtomicU64::new(0);
+        let misses = AtomicU64::new(0);
+        Self { backend, hash, hits, misses }
+    }
+
+    pub fn key<'a>(&self, operation_type: OperationType, inputs: &'a [Arc<T>]) -> CacheKey<'a, T> {
+        let hash = (self.hash)(operation_type, inputs);
+        CacheKey { hash, operation_type, inputs }
+    }
+
+    pub fn get(&self, key: &CacheKey<'_, T>) -> Option<T> {
+        let value = self.backend.get(key);
+        let counter = if value.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    pub fn insert(&self, key: &CacheKey<'_, T>, value: T) {
+        self.backend.insert(key, value)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        CacheStats { hits, misses }
+    }
+}
+
+/// Value kept in memory along with the full key it was computed for
+struct Entry<T> {
+    operation_type: OperationType,
+    inputs: Vec<Arc<T>>,
+    value: T,
+}
+
+struct LruState<T> {
+    /// Entries by hash, along with the tick of their last use
+    entries: HashMap<u64, (Entry<T>, u64)>,
+    /// Hashes ordered from least to most recently used
+    order: BTreeMap<u64, u64>,
+    tick: u64,
+}
+
+impl<T> LruState<T> {
+    fn touch(&mut self, key: u64) {
+        self.tick += 1;
+        if let Some((_, tick)) = self.entries.get_mut(&key) {
+            self.order.remove(tick);
+            *tick = self.tick;
+            self.order.insert(self.tick, key);
+        }
+    }
+}
+
+/// In-memory cache evicting the least recently used result once full.  Every
+/// entry keeps the inputs it was computed from, to tell apart keys sharing a
+/// hash, so they stay in memory as long as the entry does.
+pub struct MemoryCache<T> {
+    capacity: usize,
+    state: Mutex<LruState<T>>,
+}
+
+impl<T> MemoryCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        let entries = HashMap::new();
+        let order = BTreeMap::new();
+        let tick = 0;
+        let state = Mutex::new(LruState { entries, order, tick });
+        Self { capacity, state }
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync> ResultCache<T> for MemoryCache<T> {
+    fn get(&self, key: &CacheKey<'_, T>) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        let (entry, _) = state.entries.get(&key.hash)?;
+        if entry.operation_type != key.operation_type || entry.inputs.as_slice() != key.inputs {
+            return None;
+        }
+        let value = entry.value.clone();
+        state.touch(key.hash);
+        Some(value)
+    }
+
+    fn insert(&self, key: &CacheKey<'_, T>, value: T) {
+        let entry = Entry { operation_type: key.operation_type, inputs: key.inputs.to_vec(), value };
+        let key = key.hash;
+        let mut state = self.state.lock().unwrap();
+        if let Some((_, tick)) = state.entries.remove(&key) {
+            state.order.remove(&tick);
+        } else if state.entries.len() >= self.capacity {
+            let oldest = state.order.iter().next().map(|(tick, key)| (*tick, *key));
+            if let Some((tick, oldest)) = oldest {
+                state.order.remove(&tick);
+                state.entries.remove(&oldest);
+            }
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        state.entries.insert(key, (entry, tick));
+        state.order.insert(tick, key);
+    }
+}
+
+/// Number of temporary files created by this process, to name them uniquely
+#[cfg(feature = "serialize")]
+static TEMPORARY_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// On-disk cache storing every result as a file in a directory, so that
+/// results survive between processes.  A file holds two JSON lines, the
+/// operation type and inputs, compared on every lookup, then the value.  Files
+/// are named after a stable hash of the first line, so that they remain valid
+/// across Rust releases.  Unreadable or mismatched entries count as misses.
+#[cfg(feature = "serialize")]
+pub struct DiskCache {
+    directory: PathBuf,
+}
+
+#[cfg(feature = "serialize")]
+impl DiskCache {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> std::io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+
+    /// First line of the entry for the key, along with the path of its file
+    fn line<T: Serialize>(&self, key: &CacheKey<'_, T>) -> Option<(Vec<u8>, PathBuf)> {
+        let inputs: Vec<&T> = key.inputs.iter().map(|input| &**input).collect();
+        let mut line = serde_json::to_vec(&(key.operation_type, inputs)).ok()?;
+        line.push(b'\n');
+        let mut hasher = StableHasher::new();
+        hasher.write_field(&line);
+        let path = self.directory.join(format!("{:016x}.jsonl", hasher.finish()));
+        Some((line, path))
+    }
+}
+
+#[cfg(feature = "serialize")]
+impl<T: Serialize + DeserializeOwned> ResultCache<T> for DiskCache {
+    fn get(&self, key: &CacheKey<'_, T>) -> Option<T> {
+        let (line, path) = self.line(key)?;
+        let contents = fs::read(path).ok()?;
+        let value = contents.strip_prefix(line.as_slice())?;
+        serde_json::from_slice(value).ok()
+    }
+
+    fn insert(&self, key: &CacheKey<'_, T>, value: T) {
+        let (mut contents, path) = match self.line(key) {
+            Some(entry) => entry,
+            None => return,
+        };
+        if serde_json::to_writer(&mut contents, &value).is_err() {
+            return;
+        }
+        // Write to a temporary file first so that readers never see a partial
+        // entry, named uniquely so that concurrent writers of the same key,
+        // in this process or another, never share it
+        let count = TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed);
+        let temporary = path.with_extension(format!("{}-{}.tmp", std::process::id(), count));
+        if fs::write(&temporary, contents).is_err() || fs::rename(&temporary, &path).is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn key_depends_on_operation_and_inputs() {
+        let key = cache_key(OperationType::Sum, &[1, 2]);
+        assert_eq!(key, cache_key(OperationType::Sum, &[1, 2]));
+        assert_ne!(key, cache_key(OperationType::Product, &[1, 2]));
+        assert_ne!(key, cache_key(OperationType::Sum, &[1, 3]));
+        assert_eq!(key, cache_key(OperationType::Sum, &[Arc::new(1), Arc::new(2)]));
+    }
+
+    fn key<T>(hash: u64, inputs: &[Arc<T>]) -> CacheKey<'_, T> {
+        CacheKey { hash, operation_type: OperationType::Sum, inputs }
+    }
+
+    #[test]
+    pub fn memory_cache_evicts_least_recently_used() {
+        let inputs = [Arc::new(1)];
+        let cache = MemoryCache::new(2);
+        cache.insert(&key(1, &inputs), 10);
+        cache.insert(&key(2, &inputs), 20);
+        assert_eq!(cache.get(&key(1, &inputs)), Some(10));
+        cache.insert(&key(3, &inputs), 30);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&key(2, &inputs)), None);
+        assert_eq!(cache.get(&key(1, &inputs)), Some(10));
+        assert_eq!(cache.get(&key(3, &inputs)), Some(30));
+        cache.insert(&key(3, &inputs), 31);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&key(3, &inputs)), Some(31));
+    }
+
+    #[test]
+    pub fn memory_cache_checks_full_key() {
+        let cache = MemoryCache::new(2);
+        cache.insert(&key(1, &[Arc::new(1)]), 10);
+        // Same hash, but another key
+        assert_eq!(cache.get(&key(1, &[Arc::new(2)])), None);
+        let inputs = [Arc::new(1)];
+        let product = CacheKey { hash: 1, operation_type: OperationType::Product, inputs: &inputs };
+        assert_eq!(cache.get(&product), None);
+        assert_eq!(cache.get(&key(1, &inputs)), Some(10));
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    pub fn disk_cache_round_trip() {
+        let directory = std::env::temp_dir().join(format!("dag-cache-{}", std::process::id()));
+        let inputs = [Arc::new(1u64), Arc::new(2)];
+        let cache = DiskCache::new(&directory).unwrap();
+        assert_eq!(cache.get(&key(0, &inputs)), None);
+        cache.insert(&key(0, &inputs), 42u64);
+        assert_eq!(cache.get(&key(0, &inputs)), Some(42));
+        // The hash of the process is not used on disk
+        let cache = DiskCache::new(&directory).unwrap();
+        assert_eq!(cache.get(&key(7, &inputs)), Some(42));
+        assert_eq!(cache.get(&key(0, &inputs[..1])), None);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    pub fn disk_cache_checks_full_key() {
+        let directory = std::env::temp_dir().join(format!("dag-cache-key-{}", std::process::id()));
+        let inputs = [Arc::new(1u64)];
+        let cache = DiskCache::new(&directory).unwrap();
+        // Another key stored in the file of this one, as after a hash collision
+        let (_, path) = cache.line(&key(0, &inputs)).unwrap();
+        fs::write(&path, "[\"Sum\",[2]]\n42").unwrap();
+        assert_eq!(cache.get(&key(0, &inputs)), None::<u64>);
+        cache.insert(&key(0, &inputs), 43u64);
+        assert_eq!(cache.get(&key(0, &inputs)), Some(43));
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    pub fn disk_cache_concurrent_writers() {
+        let directory = std::env::temp_dir().join(format!("dag-cache-writers-{}", std::process::id()));
+        let cache = Arc::new(DiskCache::new(&directory).unwrap());
+        let writers: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    let inputs = [Arc::new(1u64)];
+                    (0..50).for_each(|_| cache.insert(&key(0, &inputs), 42u64));
+                })
+            })
+            .collect();
+        writers.into_iter().for_each(|writer| writer.join().unwrap());
+        assert_eq!(cache.get(&key(0, &[Arc::new(1u64)])), Some(42));
+        // Every temporary file was renamed
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[cfg(feature = "serialize")]
+    #[test]
+    pub fn disk_cache_file_names_are_stable() {
+        let directory = std::env::temp_dir().join(format!("dag-cache-name-{}", std::process::id()));
+        let cache = DiskCache::new(&directory).unwrap();
+        let (line, path) = cache.line(&key(0, &[Arc::new(1u64), Arc::new(2)])).unwrap();
+        assert_eq!(line, b"[\"Sum\",[1,2]]\n");
+        assert_eq!(path.file_name().unwrap(), "f91a34187b132cf3.jsonl");
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Shared flag used to stop an in-flight computation.  Clones of a token all
+/// observe the same cancellation.
+#[derive(Clone)]
+pub struct CancellationToken {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        let sender = Arc::new(sender);
+        Self { sender, receiver }
+    }
+
+    pub fn cancel(&self) {
+        // Every clone holds a receiver, so this can never fail
+        let _ = self.sender.broadcast(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Resolves once the token has been cancelled
+    pub async fn cancelled(&self) {
+        let mut receiver = self.receiver.clone();
+        while !*receiver.borrow() {
+            if receiver.recv().await.is_none() {
+                return;
+            }
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cancels the token when dropped, unless disarmed first
+pub struct CancelOnDrop {
+    token: Option<CancellationToken>,
+}
+
+impl CancelOnDrop {
+    pub fn new(token: CancellationToken) -> Self {
+        let token = Some(token);
+        Self { token }
+    }
+
+    pub fn disarm(mut self) {
+        self.token = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = &self.token {
+            token.cancel();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    pub async fn cancel_wakes_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        let waiter = tokio::spawn(async move { clone.cancelled().await });
+        assert!(!token.is_cancelled());
+        token.cancel();
+        timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+        assert!(token.is_cancelled());
+        token.cancelled().await;
+    }
+
+    #[tokio::test]
+    pub async fn cancel_on_drop() {
+        let token = CancellationToken::new();
+        CancelOnDrop::new(token.clone()).disarm();
+        assert!(!token.is_cancelled());
+        drop(CancelOnDrop::new(token.clone()));
+        assert!(token.is_cancelled());
+    }
+}
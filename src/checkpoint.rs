@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::dag::{Dag, NodeId};
+
+/// Version of the checkpoint file format
+const VERSION: u32 = 2;
+
+/// First line of a checkpoint file
+#[derive(Serialize)]
+struct Header<'a, T> {
+    version: u32,
+    fingerprint: u64,
+    /// Value sent to the start nodes without an input of their own
+    initial: &'a T,
+    /// Inputs set for some start nodes, ordered by id
+    inputs: BTreeMap<NodeId, &'a T>,
+}
+
+impl<'a, T: Serialize> Header<'a, T> {
+    fn new(dag: &Dag, initial: &'a T, inputs: &'a HashMap<NodeId, T>) -> Self {
+        let inputs = inputs.iter().map(|(id, input)| (*id, input)).collect();
+        Self { version: VERSION, fingerprint: dag.fingerprint(), initial, inputs }
+    }
+
+    fn line(&self) -> Result<String, CheckpointError> {
+        serde_json::to_string(self).map_err(format_error)
+    }
+}
+
+/// Start of the header, checked before the inputs
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+    fingerprint: u64,
+}
+
+/// Every following line, one per completed node
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    id: NodeId,
+    value: T,
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// The file is not a valid checkpoint
+    Format(String),
+    /// The checkpoint was written for a different DAG
+    Stale { expected: u64, found: u64 },
+    /// The checkpoint was written for the same DAG, but with other inputs
+    InputsChanged,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(error) => write!(f, "checkpoint I/O error: {}", error),
+            CheckpointError::Format(message) => write!(f, "invalid checkpoint: {}", message),
+            CheckpointError::Stale { expected, found } =>
+                write!(f, "checkpoint is for DAG {:016x}, not {:016x}", found, expected),
+            CheckpointError::InputsChanged => write!(f, "checkpoint was written with other inputs"),
+        }
+    }
+}
+
+impl Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(error: io::Error) -> Self {
+        CheckpointError::Io(error)
+    }
+}
+
+fn format_error(error: serde_json::Error) -> CheckpointError {
+    CheckpointError::Format(error.to_string())
+}
+
+/// Append-only file of completed node results, written as JSON lines after a
+/// header identifying the DAG and its inputs, so that a crashed computation can
+/// be resumed
+pub struct Checkpoint {
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Starts a new checkpoint for the DAG run with the given initial value and
+    /// inputs of start nodes, replacing any existing file
+    pub fn create<T, P>(path: P, dag: &Dag, initial: &T, inputs: &HashMap<NodeId, T>) -> Result<Self, CheckpointError>
+    where T: Serialize, P: AsRef<Path> {
+        let line = Header::new(dag, initial, inputs).line()?;
+        let mut file = File::create(path)?;
+        writeln!(file, "{}", line)?;
+        file.sync_data()?;
+        let file = Mutex::new(file);
+        Ok(Self { file })
+    }
+
+    /// Reopens an existing checkpoint for the DAG, returning the results of
+    /// every node completed so far.  A checkpoint written for another DAG, or
+    /// for other inputs, is rejected, and a truncated last line, from a crash
+    /// in the middle of a write, is ignored.
+    pub fn resume<T, P>(path: P, dag: &Dag, initial: &T, inputs: &HashMap<NodeId, T>)
+    -> Result<(Self, HashMap<NodeId, T>), CheckpointError>
+    where T: Serialize + DeserializeOwned, P: AsRef<Path> {
+        let contents = fs::read_to_string(&path)?;
+        let complete = &contents[..contents.rfind('\n').map_or(0, |index| index + 1)];
+        let mut lines = complete.lines();
+        let header = lines.next().ok_or_else(|| CheckpointError::Format("missing header".to_owned()))?;
+        let version: Version = serde_json::from_str(header).map_err(format_error)?;
+        if version.version != VERSION {
+            return Err(CheckpointError::Format(format!("unsupported version {}", version.version)));
+        }
+        let expected = Header::new(dag, initial, inputs);
+        if version.fingerprint != expected.fingerprint {
+            return Err(CheckpointError::Stale { expected: expected.fingerprint, found: version.fingerprint });
+        }
+        if header != expected.line()? {
+            return Err(CheckpointError::InputsChanged);
+        }
+
+        let mut values = HashMap::new();
+        for line in lines {
+            let entry: Entry<T> = serde_json::from_str(line).map_err(format_error)?;
+            values.insert(entry.id, entry.value);
+        }
+
+        // Drop any partial last line, so that appending new entries keeps the file valid
+        let file = OpenOptions::new().append(true).open(&path)?;
+        file.set_len(complete.len() as u64)?;
+        let file = Mutex::new(file);
+        Ok((Self { file }, values))
+    }
+
+    /// Appends the result of a node, written straight to the file so that it
+    /// survives a crash of the process
+    pub fn record<T: Serialize>(&self, id: NodeId, value: &T) -> Result<(), CheckpointError> {
+        let entry = Entry { id, value };
+        let line = serde_json::to_string(&entry).map_err(format_error)?;
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+
+    use crate::computation::Computation;
+    use crate::operation::{Operation, OperationType};
+
+    fn checkpoint_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dag-checkpoint-{}-{}", name, std::process::id()))
+    }
+
+    fn delay_dag() -> (Dag, Vec<NodeId>) {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Delay), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id1, id2]);
+        (dag, vec![id1, id2, id3])
+    }
+
+    #[tokio::test]
+    pub async fn resume_skips_completed_nodes() {
+        let path = checkpoint_path("resume");
+        let (dag, ids) = delay_dag();
+        {
+            // Simulates a crash right after the delay finished
+            let checkpoint = Checkpoint::create(&path, &dag, &3u64, &HashMap::new()).unwrap();
+            checkpoint.record(ids[0], &3u64).unwrap();
+            checkpoint.record(ids[1], &0u64).unwrap();
+        }
+
+        let (checkpoint, completed) = Checkpoint::resume(&path, &dag, &3u64, &HashMap::new()).unwrap();
+        assert_eq!(completed.len(), 2);
+        let mut computation = Computation::new(&dag);
+        computation.set_checkpoint(checkpoint, completed);
+        let start = Instant::now();
+        let report = computation.process(3).await;
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(report.results, BTreeMap::from([(ids[2], 3)]));
+
+        let (_, completed) = Checkpoint::resume(&path, &dag, &3u64, &HashMap::new()).unwrap();
+        assert_eq!(completed.len(), 3);
+        assert_eq!(completed[&ids[2]], 3);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn full_run_is_recorded() {
+        let path = checkpoint_path("full");
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Product), vec![id1, id1]);
+        let mut computation = Computation::new(&dag);
+        computation.set_checkpoint(Checkpoint::create(&path, &dag, &4u64, &HashMap::new()).unwrap(), HashMap::new());
+        computation.process(4u64).await;
+        let (_, completed) = Checkpoint::resume(&path, &dag, &4u64, &HashMap::new()).unwrap();
+        assert_eq!(completed[&id1], 4);
+        assert_eq!(completed[&id2], 16);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    pub fn stale_checkpoint_rejected() {
+        let path = checkpoint_path("stale");
+        let (mut dag, ids) = delay_dag();
+        Checkpoint::create(&path, &dag, &3u64, &HashMap::new()).unwrap();
+        dag.add_node(Operation::new(OperationType::Sum), vec![ids[2]]);
+        match Checkpoint::resume(&path, &dag, &3u64, &HashMap::new()) {
+            Err(CheckpointError::Stale { expected, .. }) => assert_eq!(expected, dag.fingerprint()),
+            _ => panic!("Stale checkpoint accepted"),
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    pub fn other_inputs_rejected() {
+        let path = checkpoint_path("inputs");
+        let (dag, ids) = delay_dag();
+        let inputs: HashMap<NodeId, u64> = vec![(ids[0], 5)].into_iter().collect();
+        Checkpoint::create(&path, &dag, &3u64, &inputs).unwrap();
+        assert!(Checkpoint::resume::<u64, _>(&path, &dag, &3, &inputs).is_ok());
+        assert!(matches!(Checkpoint::resume(&path, &dag, &4u64, &inputs), Err(CheckpointError::InputsChanged)));
+        assert!(matches!(Checkpoint::resume(&path, &dag, &3u64, &HashMap::new()), Err(CheckpointError::InputsChanged)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    pub fn partial_line_ignored() {
+        let path = checkpoint_path("partial");
+        let (dag, ids) = delay_dag();
+        let checkpoint = Checkpoint::create(&path, &dag, &3u64, &HashMap::new()).unwrap();
+        checkpoint.record(ids[0], &3u64).unwrap();
+        drop(checkpoint);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{{\"id\": 2, \"val").unwrap();
+
+        let (checkpoint, completed) = Checkpoint::resume(&path, &dag, &3u64, &HashMap::new()).unwrap();
+        assert_eq!(completed.len(), 1);
+        checkpoint.record(ids[1], &0u64).unwrap();
+        let (_, completed) = Checkpoint::resume(&path, &dag, &3u64, &HashMap::new()).unwrap();
+        assert_eq!(completed.len(), 2);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,10 +1,35 @@
-use std::collections::HashMap;
-use std::thread;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::panic::AssertUnwindSafe;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
 use tokio::sync::oneshot::{Receiver, Sender, channel};
-use futures::future::join_all;
+use tokio::time::{Instant, delay_for, delay_until, timeout};
+use futures::future::{FutureExt, join_all, pending};
+use tracing::{Instrument, Span, debug, debug_span, error, field, info_span, warn};
 
+use crate::cache::{ResultCache, RunCache, cache_key};
+use crate::cancel::{CancelOnDrop, CancellationToken};
+#[cfg(feature = "serialize")]
+use crate::checkpoint::Checkpoint;
 use crate::dag::{Dag, NodeId};
-use crate::operation::{Operable, Operation};
+use crate::debugger::{DebugSession, Debugger, Pause};
+use crate::error::{ComputationError, NodeError};
+use crate::fault::{Fault, FaultInjector};
+use crate::fusion;
+use crate::hook::{self, NodeHook};
+use crate::lineage::LineageRecorder;
+use crate::metrics::Metrics;
+use crate::operation::{Operable, Operation, OperationType};
+use crate::progress::{ProgressCallback, ProgressEvent, ProgressTracker};
+use crate::report::{NodeStatus, Report};
+use crate::retry::{Attempt, RetryPolicy};
+use crate::schedule::{Arrival, CostModel, Permit, Scheduler, SchedulingPolicy, remaining_paths};
+use crate::watchdog::Watchdog;
 
 pub trait Sendable: Send + Sync {}
 impl<T: Send + Sync> Sendable for T {}
@@ -13,122 +38,911 @@ pub struct ComputationNode<T>
 where for<'a> T: Operable<'a, T> + Sendable + 'static {
     id: NodeId,
     operation: Operation,
-    receivers: Vec<Receiver<T>>,
-    senders: Vec<Sender<T>>,
-    debug: bool,
+    /// Values are shared between a parent and all of its children, instead of
+    /// being copied for every child
+    receivers: Vec<Receiver<Arc<T>>>,
+    senders: Vec<Sender<Arc<T>>>,
+    retry_policy: RetryPolicy,
+    attempts: Vec<Attempt>,
+    /// Result known in advance, sent to the children without running the operation
+    result: Option<T>,
+    /// Whether to report the result once computed
+    retain: bool,
+    /// Whether the only child is the next node of a fused chain, which takes
+    /// the result directly instead of through a channel
+    fused: bool,
+}
+
+/// What to do with the rest of the computation once a node fails
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExecutionPolicy {
+    /// Stop every other node as soon as one node fails
+    FailFast,
+    /// Keep running every branch that does not depend on a failed node
+    #[default]
+    ContinueOnError,
+}
+
+/// Called with the result of every node as soon as it is computed
+pub type Recorder<T> = Box<dyn Fn(NodeId, &T) + Send + Sync>;
+
+/// State shared by every node task of a single run
+pub struct RunContext<T> {
+    deadline: Option<Instant>,
+    token: CancellationToken,
+    /// Cancelled internally when the execution policy requires stopping early
+    abort: CancellationToken,
+    policy: ExecutionPolicy,
+    cache: Option<RunCache<T>>,
+    recorder: Option<Recorder<T>>,
+    /// Limits how many nodes run at once, when set
+    scheduler: Option<Scheduler>,
+    /// Priority of every node waiting for the scheduler, higher runs first
+    priorities: HashMap<NodeId, Duration>,
+    metrics: Option<Arc<Metrics>>,
+    progress: Option<ProgressTracker>,
+    hooks: Vec<Box<dyn NodeHook<T>>>,
+    faults: Option<FaultInjector>,
+    watchdog: Option<Watchdog>,
+    debugger: Option<DebugSession<T>>,
+    lineage: Option<LineageRecorder<T>>,
+}
+
+impl<T> RunContext<T> {
+    fn is_stopped(&self) -> bool {
+        self.token.is_cancelled() || self.abort.is_cancelled()
+    }
+}
+
+/// Everything a node reports back once its task is done
+pub struct NodeOutcome<T> {
+    pub status: NodeStatus,
+    pub attempts: Vec<Attempt>,
+    /// The computed result, only kept if requested
+    pub value: Option<Arc<T>>,
+    /// Passed to the next node of a fused chain, if the node completed
+    handoff: Option<Handoff<T>>,
+}
+
+/// Where a node gets its inputs from
+pub(crate) enum Source<T> {
+    /// A channel from every parent, or from the initial value for a start node
+    Channels,
+    /// The previous node of its fused chain, which is its only parent, if it
+    /// completed
+    Chain(Option<Handoff<T>>),
+}
+
+/// Result of a node passed straight to the next node of its fused chain, along
+/// with the slot it ran in
+pub(crate) struct Handoff<T> {
+    value: Arc<T>,
+    permit: Option<Permit>,
 }
 
 impl<T> ComputationNode<T>
 where for<'a> T: Operable<'a, T> + Sendable + 'static {
-    pub fn new(id: NodeId, operation: Operation, debug: bool) -> Self {
+    pub fn new(id: NodeId, operation: Operation) -> Self {
         let receivers = vec![];
         let senders = vec![];
-        ComputationNode { id, operation, receivers, senders, debug }
+        let retry_policy = Default::default();
+        let attempts = vec![];
+        let result = None;
+        let retain = false;
+        let fused = false;
+        ComputationNode { id, operation, receivers, senders, retry_policy, attempts, result, retain, fused }
+    }
+
+    /// Uses the given value as this node's result, without waiting for inputs
+    /// or running the operation
+    pub fn set_result(&mut self, result: T) {
+        self.result = Some(result);
     }
 
-    pub fn add_input(&mut self, receiver: Receiver<T>) {
+    /// Reports the result in the node's outcome
+    pub fn retain(&mut self) {
+        self.retain = true;
+    }
+
+    /// Hands the result to the only child, run next in the same task, instead
+    /// of sending it
+    pub(crate) fn fuse(&mut self) {
+        self.fused = true;
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    pub fn add_input(&mut self, receiver: Receiver<Arc<T>>) {
         self.receivers.push(receiver)
     }
 
-    pub fn add_output(&mut self, sender: Sender<T>) {
+    pub fn add_output(&mut self, sender: Sender<Arc<T>>) {
         self.senders.push(sender)
     }
 
-    pub async fn process(mut self) {
-        let mut inputs = vec![];
-        for receiver in &mut self.receivers {
-            let value = receiver.await.unwrap();
-            inputs.push(value);
+    /// Waits for all inputs, or takes the one handed over in a fused chain,
+    /// runs the operation, and sends the result to all outputs.  If any input
+    /// never arrives, or the operation fails, the senders are dropped so that
+    /// every descendant is skipped.  Everything
+    /// happens in a `node` span, child of the span current at the time of the call.
+    pub(crate) fn process(self, context: Arc<RunContext<T>>, source: Source<T>) -> impl Future<Output = NodeOutcome<T>> {
+        let span = debug_span!(
+            "node",
+            id = self.id,
+            operation = ?self.operation.operation_type,
+            inputs = field::Empty,
+            duration = field::Empty,
+        );
+        let id = self.id;
+        async move {
+            let outcome = self.execute(context.clone(), source).await;
+            if let Some(progress) = &context.progress {
+                progress.finished(id, outcome.status.clone());
+            }
+            if let Some(watchdog) = &context.watchdog {
+                watchdog.finished(id);
+            }
+            outcome
+        }.instrument(span)
+    }
+
+    async fn execute(mut self, context: Arc<RunContext<T>>, source: Source<T>) -> NodeOutcome<T> {
+        if let Some(result) = self.result.take() {
+            if let Some(progress) = &context.progress {
+                progress.started(self.id);
+            }
+            if let Some(watchdog) = &context.watchdog {
+                watchdog.started(self.id);
+            }
+            if let Some(lineage) = &context.lineage {
+                lineage.record(self.id, self.operation.operation_type, &[], &result);
+            }
+            return self.complete(&context, result);
+        }
+        // Slot handed over by the previous node of a fused chain
+        let mut held = None;
+        let collected = match source {
+            Source::Channels => {
+                let receivers = std::mem::take(&mut self.receivers);
+                interruptible(&context, collect_inputs(receivers)).await
+            },
+            Source::Chain(handoff) => Ok(handoff.map(|handoff| {
+                held = handoff.permit;
+                vec![handoff.value]
+            })),
+        };
+        let mut inputs = match collected {
+            Ok(Some(inputs)) => inputs,
+            // A parent may have stopped because of the cancellation before this node noticed it
+            Ok(None) if context.is_stopped() => return self.outcome(NodeStatus::Cancelled),
+            Ok(None) | Err(Interrupt::Deadline) => return self.outcome(NodeStatus::Skipped),
+            Err(Interrupt::Cancelled) => return self.outcome(NodeStatus::Cancelled),
+        };
+        Span::current().record("inputs", inputs.len());
+        let ready = Instant::now();
+        let mut arrival = context.scheduler.as_ref().and_then(|scheduler| scheduler.arrival(self.id));
+        let operation_type = self.operation.operation_type;
+        let (hooked, rejected) = hook::before(&context.hooks, self.id, &self.operation, &mut inputs);
+        let rejected = rejected.err();
+        // Held until the node completes when stepping through nodes
+        let mut step = None;
+        let mut overridden = None;
+        // Held until the result is sent, so that ready children compete for the slot
+        let mut permit = None;
+        if let (Some(debugger), None) = (&context.debugger, &rejected) {
+            // Waiting for a slot while holding the debugger would keep the
+            // other ready nodes from arriving, so no slot would ever be free
+            match self.acquire(&context, arrival.take(), held.take()).await {
+                Ok(slot) => permit = slot,
+                Err(Interrupt::Deadline) => return self.outcome(NodeStatus::Skipped),
+                Err(Interrupt::Cancelled) => return self.outcome(NodeStatus::Cancelled),
+            }
+            // Waiting on the user is no stall
+            let _paused = context.watchdog.as_ref().map(Watchdog::pause);
+            match interruptible(&context, debugger.pause(self.id, operation_type, &inputs)).await {
+                Ok((value, guard)) => {
+                    overridden = value;
+                    step = guard;
+                },
+                Err(Interrupt::Deadline) => return self.outcome(NodeStatus::Skipped),
+                Err(Interrupt::Cancelled) => return self.outcome(NodeStatus::Cancelled),
+            }
+        }
+        let key = context.cache.as_ref()
+            .filter(|_| rejected.is_none() && overridden.is_none())
+            .map(|cache| cache.key(operation_type, &inputs));
+        let cached = context.cache.as_ref().zip(key.as_ref()).and_then(|(cache, key)| cache.get(key));
+        let hit = cached.is_some();
+        let computed = rejected.is_none() && overridden.is_none() && !hit;
+        if computed && permit.is_none() {
+            match self.acquire(&context, arrival.take(), held.take()).await {
+                Ok(slot) => permit = slot,
+                Err(Interrupt::Deadline) => return self.outcome(NodeStatus::Skipped),
+                Err(Interrupt::Cancelled) => return self.outcome(NodeStatus::Cancelled),
+            }
+        }
+        // Only the operation needs a slot
+        drop(held);
+        // Every node producing a value or an error starts, however it does
+        if let Some(progress) = &context.progress {
+            progress.started(self.id);
+        }
+        if let Some(watchdog) = &context.watchdog {
+            watchdog.started(self.id);
+        }
+        let mut result = if let Some(error) = rejected {
+            Err(error)
+        } else if let Some(value) = overridden {
+            debug!("using value set in the debugger");
+            Ok(value)
+        } else if let Some(value) = cached {
+            debug!("using cached result");
+            Ok(value)
+        } else {
+            debug!("processing node");
+            let running = context.metrics.as_ref().map(|metrics| metrics.start_node(ready.elapsed()));
+            let start = Instant::now();
+            let result = interruptible(&context, self.run_with_retries(&inputs, context.faults.as_ref())).await;
+            let duration = start.elapsed();
+            Span::current().record("duration", field::debug(duration));
+            // A cancelled node neither completed nor failed
+            if let Some(running) = running.filter(|_| !matches!(result, Err(Interrupt::Cancelled))) {
+                running.finish(operation_type, duration, !matches!(result, Ok(Ok(_))));
+            }
+            match result {
+                Ok(result) => result,
+                Err(Interrupt::Deadline) => Err(NodeError::DeadlineExceeded { id: self.id, operation_type }),
+                Err(Interrupt::Cancelled) => return self.outcome(NodeStatus::Cancelled),
+            }
+        };
+        // The cache keeps the result of the operation itself, before any hook changes it
+        if let (Some(cache), Some(key), false, Ok(value)) = (&context.cache, &key, hit, &result) {
+            cache.insert(key, value.clone());
+        }
+        hook::after(&context.hooks[..hooked], self.id, &self.operation, &inputs, &mut result);
+        match result {
+            Ok(result) => {
+                if let Some(recorder) = &context.recorder {
+                    recorder(self.id, &result);
+                }
+                if let Some(debugger) = &context.debugger {
+                    debugger.record(self.id, &inputs, &result);
+                }
+                if let Some(lineage) = &context.lineage {
+                    lineage.record(self.id, operation_type, &inputs, &result);
+                }
+                let mut outcome = self.complete(&context, result);
+                if let Some(handoff) = &mut outcome.handoff {
+                    // The next node of the chain may go on running in the same slot
+                    handoff.permit = permit.take();
+                }
+                drop(permit);
+                drop(step);
+                outcome
+            },
+            Err(error) => {
+                warn!(%error, "node failed");
+                if context.policy == ExecutionPolicy::FailFast {
+                    context.abort.cancel();
+                }
+                self.outcome(NodeStatus::Failed(error))
+            },
+        }
+    }
+
+    /// Waits for a slot to run in if the number of running nodes is limited,
+    /// giving up the arrival of the node.  The slot handed over by the previous
+    /// node of a fused chain is kept if no other node comes first.
+    async fn acquire(&self, context: &RunContext<T>, arrival: Option<Arrival>, held: Option<Permit>)
+    -> Result<Option<Permit>, Interrupt> {
+        let scheduler = match &context.scheduler {
+            Some(scheduler) => scheduler,
+            None => return Ok(None),
+        };
+        let priority = context.priorities.get(&self.id).copied().unwrap_or_default();
+        let arrival = match held {
+            Some(permit) => match scheduler.hand_over(permit, priority, arrival) {
+                Ok(permit) => return Ok(Some(permit)),
+                Err(arrival) => arrival,
+            },
+            None => arrival,
+        };
+        interruptible(context, scheduler.acquire(priority, arrival)).await.map(Some)
+    }
+
+    /// Sends the result to all outputs, or hands it to the next node of the
+    /// chain, sharing it rather than copying it
+    fn complete(mut self, context: &RunContext<T>, result: T) -> NodeOutcome<T> {
+        if let Some(scheduler) = &context.scheduler {
+            scheduler.completed(self.id);
         }
-        if self.debug {
-            println!("{:?}: processing node {}", thread::current().id(), self.id);
+        let result = Arc::new(result);
+        // A closed receiver only means that the child was skipped
+        std::mem::take(&mut self.senders).into_iter()
+            .for_each(|sender| { let _ = sender.send(result.clone()); });
+        let handoff = self.fused.then(|| Handoff { value: result.clone(), permit: None });
+        let value = if self.retain { Some(result) } else { None };
+        NodeOutcome { status: NodeStatus::Completed, attempts: self.attempts, value, handoff }
+    }
+
+    fn outcome(self, status: NodeStatus) -> NodeOutcome<T> {
+        NodeOutcome { status, attempts: self.attempts, value: None, handoff: None }
+    }
+
+    /// Runs the operation until it succeeds or the retry policy gives up,
+    /// recording every attempt
+    async fn run_with_retries(&mut self, inputs: &[Arc<T>], faults: Option<&FaultInjector>) -> Result<T, NodeError> {
+        loop {
+            let attempt = self.attempts.len() as u32 + 1;
+            let fault = faults.and_then(|faults| faults.fault(self.id, attempt));
+            let start = Instant::now();
+            let result = self.run(inputs, fault).await;
+            let duration = start.elapsed();
+            let error = result.as_ref().err().cloned();
+            self.attempts.push(Attempt { duration, error });
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            match self.retry_policy.next_delay(attempt, &error) {
+                Some(delay) => {
+                    debug!(%error, ?delay, "retrying node");
+                    delay_for(delay).await;
+                },
+                None => return Err(error),
+            }
         }
-        let result = self.operation.process(&inputs).await;
-        self.senders.into_iter().for_each(|sender| { sender.send(result.clone()).expect("Error sending"); });
+    }
+
+    /// Runs the operation once, disrupted by the injected fault if any, which
+    /// counts towards the operation timeout
+    async fn run(&self, inputs: &[Arc<T>], fault: Option<Fault>) -> Result<T, NodeError> {
+        let id = self.id;
+        let operation_type = self.operation.operation_type;
+        let operation = async {
+            match fault {
+                Some(Fault::Fail) => return Err(NodeError::Injected { id, operation_type }),
+                Some(Fault::Hang) => pending::<()>().await,
+                Some(Fault::Delay(delay)) => delay_for(delay).await,
+                None => {},
+            }
+            AssertUnwindSafe(self.operation.process(inputs)).catch_unwind().await.map_err(|panic| {
+                let message = panic_message(panic);
+                NodeError::Panicked { id, operation_type, message }
+            })
+        };
+        match self.operation.timeout {
+            Some(duration) => timeout(duration, operation).await
+                .unwrap_or(Err(NodeError::TimedOut { id, operation_type, timeout: duration })),
+            None => operation.await,
+        }
+    }
+}
+
+/// Receives a value from every parent, or `None` if any parent never sends one
+async fn collect_inputs<T>(receivers: Vec<Receiver<T>>) -> Option<Vec<T>> {
+    let mut inputs = vec![];
+    for receiver in receivers {
+        inputs.push(receiver.await.ok()?);
+    }
+    Some(inputs)
+}
+
+/// Reasons for a node to stop before finishing
+enum Interrupt {
+    Deadline,
+    Cancelled,
+}
+
+/// Runs the future to completion, unless the deadline passes or the run is
+/// cancelled or aborted first
+async fn interruptible<T, F: std::future::Future>(context: &RunContext<T>, future: F) -> Result<F::Output, Interrupt> {
+    let deadline = async {
+        match context.deadline {
+            Some(deadline) => delay_until(deadline).await,
+            None => pending().await,
+        }
+    };
+    tokio::select! {
+        output = future => Ok(output),
+        _ = deadline => Err(Interrupt::Deadline),
+        _ = context.token.cancelled() => Err(Interrupt::Cancelled),
+        _ = context.abort.cancelled() => Err(Interrupt::Cancelled),
+    }
+}
+
+pub(crate) fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
     }
 }
 
 pub struct Computation<T>
 where for<'a> T: Operable<'a, T> + Sendable + 'static {
-    result_receivers: Vec<Receiver<T>>,
-    initial_senders: Vec<Sender<T>>,
+    /// Nodes receiving the initial value, or their own input
+    starts: Vec<NodeId>,
+    inputs: HashMap<NodeId, T>,
     computations: HashMap<NodeId, ComputationNode<T>>,
-    debug: bool,
+    deadline: Option<Duration>,
+    retry_policies: HashMap<NodeId, RetryPolicy>,
+    operation_retry_policies: HashMap<OperationType, RetryPolicy>,
+    token: CancellationToken,
+    policy: ExecutionPolicy,
+    children: HashMap<NodeId, Vec<NodeId>>,
+    cache: Option<RunCache<T>>,
+    targets: Option<Vec<NodeId>>,
+    pruned: Vec<NodeId>,
+    recorder: Option<Recorder<T>>,
+    concurrency: Option<NonZeroUsize>,
+    scheduling_policy: SchedulingPolicy,
+    metrics: Option<Arc<Metrics>>,
+    progress: Option<ProgressCallback>,
+    hooks: Vec<Box<dyn NodeHook<T>>>,
+    faults: Option<FaultInjector>,
+    watchdog: Option<Duration>,
+    debugger: Option<DebugSession<T>>,
+    lineage: bool,
+    /// Whether chains of nodes run as a single task
+    fusion: bool,
 }
 
 impl<T> Computation<T>
 where for<'a> T: Operable<'a, T> + Sendable + 'static {
-    pub fn new(dag: &Dag, debug: bool) -> Self {
-        if debug {
-            println!("Creating computation nodes");
-        }
+    pub fn new(dag: &Dag) -> Self {
+        debug!(nodes = dag.nodes.len(), "creating computation nodes");
         let mut computations = HashMap::new();
         dag.nodes.iter().for_each(|(id, node)| {
-            let computation = ComputationNode::new(id.to_owned(), node.operation.clone(), debug);
+            let computation = ComputationNode::new(id.to_owned(), node.operation.clone());
             computations.insert(computation.id, computation);
         });
 
-        if debug {
-            println!("Connecting senders and receivers");
+        let starts = dag.starts.clone();
+        let inputs = HashMap::new();
+        let deadline = None;
+        let retry_policies = HashMap::new();
+        let operation_retry_policies = HashMap::new();
+        let token = CancellationToken::new();
+        let policy = Default::default();
+        let children = dag.nodes.iter().map(|(id, node)| (*id, node.children.clone())).collect();
+        let cache = None;
+        let targets = None;
+        let pruned = vec![];
+        let recorder = None;
+        let concurrency = None;
+        let scheduling_policy = Default::default();
+        let metrics = None;
+        let progress = None;
+        let hooks = vec![];
+        let faults = None;
+        let watchdog = None;
+        let debugger = None;
+        let lineage = false;
+        let fusion = true;
+        Self {
+            starts, inputs, computations, deadline, retry_policies,
+            operation_retry_policies, token, policy, children, cache, targets, pruned, recorder,
+            concurrency, scheduling_policy, metrics, progress, hooks,
+            faults, watchdog, debugger, lineage, fusion,
+        }
+    }
+
+    /// Writes every computed result to the checkpoint, and uses the results of
+    /// nodes completed in a previous run instead of running them again
+    #[cfg(feature = "serialize")]
+    pub fn set_checkpoint(&mut self, checkpoint: Checkpoint, completed: HashMap<NodeId, T>)
+    where T: serde::Serialize {
+        completed.into_iter().for_each(|(id, value)| {
+            if let Some(computation) = self.computations.get_mut(&id) {
+                computation.set_result(value);
+            }
+        });
+        self.recorder = Some(Box::new(move |id, value| {
+            if let Err(error) = checkpoint.record(id, value) {
+                error!(id, %error, "error writing checkpoint");
+            }
+        }));
+    }
+
+    /// Only computes the given nodes and their ancestors, pruning every other
+    /// node.  The results are then the values of the targets, in order.  Fails
+    /// without changing anything if any target is not a node still to run.
+    pub fn set_targets(&mut self, targets: Vec<NodeId>) -> Result<(), ComputationError> {
+        targets.iter().try_for_each(|id| self.check_node(*id))?;
+        let mut parents: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        self.children.iter().for_each(|(id, children)| {
+            children.iter().for_each(|child_id| parents.entry(*child_id).or_default().push(*id));
+        });
+        let mut needed: HashSet<NodeId> = targets.iter().copied().collect();
+        let mut stack = targets.clone();
+        while let Some(id) = stack.pop() {
+            parents.get(&id).into_iter().flatten().for_each(|parent_id| {
+                if needed.insert(*parent_id) {
+                    stack.push(*parent_id);
+                }
+            });
+        }
+
+        let pruned: Vec<NodeId> = self.computations.keys().filter(|id| !needed.contains(id)).copied().collect();
+        pruned.iter().for_each(|id| { self.computations.remove(id); });
+        debug!(pruned = pruned.len(), "pruned nodes not needed by the targets");
+        self.pruned.extend(pruned);
+        targets.iter().for_each(|id| self.computations.get_mut(id).unwrap().retain());
+        self.targets = Some(targets);
+        Ok(())
+    }
+
+    /// Whether the node is still to run, rather than pruned or unknown
+    fn check_node(&self, id: NodeId) -> Result<(), ComputationError> {
+        if self.computations.contains_key(&id) {
+            Ok(())
+        } else if self.pruned.contains(&id) {
+            Err(ComputationError::PrunedNode(id))
+        } else {
+            Err(ComputationError::UnknownNode(id))
+        }
+    }
+
+    /// Sends `input` to the given start node instead of the initial value.
+    /// Fails without changing anything if the node is not a start node still
+    /// to run.
+    pub fn set_input(&mut self, id: NodeId, input: T) -> Result<(), ComputationError> {
+        self.check_node(id)?;
+        if !self.starts.contains(&id) {
+            return Err(ComputationError::NotStartNode(id));
         }
+        self.inputs.insert(id, input);
+        Ok(())
+    }
+
+    /// Uses `result` as the given node's result instead of running it, for
+    /// example when it is already known from a previous run.  Fails without
+    /// changing anything if the node is not still to run.
+    pub fn set_result(&mut self, id: NodeId, result: T) -> Result<(), ComputationError> {
+        self.check_node(id)?;
+        self.computations.get_mut(&id).unwrap().set_result(result);
+        Ok(())
+    }
+
+    /// Keeps the results of every node in `Report::values`, instead of only
+    /// the sink nodes
+    pub fn retain_values(&mut self) {
+        self.computations.values_mut().for_each(|computation| computation.retain());
+    }
+
+    /// Keeps the results of the given nodes in `Report::values`, without
+    /// having to make them sinks.  Fails without watching any node if one of
+    /// them is unknown or was pruned, since it never runs.
+    pub fn watch(&mut self, ids: &[NodeId]) -> Result<(), ComputationError> {
+        ids.iter().try_for_each(|id| self.check_node(*id))?;
+        ids.iter().for_each(|id| self.computations.get_mut(id).unwrap().retain());
+        Ok(())
+    }
+
+    /// Looks up every node's result in the cache before running it, keyed by
+    /// its operation type and inputs, and stores newly computed results.
+    /// Only suitable for pure operations.
+    pub fn set_cache<C>(&mut self, cache: Arc<C>)
+    where T: Hash, C: ResultCache<T> + 'static {
+        self.cache = Some(RunCache::new(cache, cache_key::<Arc<T>>));
+    }
+
+    /// Chooses between stopping at the first failure or running every
+    /// independent branch, `ContinueOnError` by default
+    pub fn set_execution_policy(&mut self, policy: ExecutionPolicy) {
+        self.policy = policy;
+    }
+
+    /// Token stopping this computation when cancelled.  Dropping the future
+    /// returned by `process` stops every node task as well, but leaves the
+    /// token as it is.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Stops this computation when the given token is cancelled, instead of
+    /// its own token.  The token may be shared with other computations, since
+    /// this one never cancels it.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.token = token;
+    }
+
+    /// Retries the given node according to `retry_policy`, taking precedence
+    /// over any policy set for its operation type
+    pub fn set_retry_policy(&mut self, id: NodeId, retry_policy: RetryPolicy) {
+        self.retry_policies.insert(id, retry_policy);
+    }
+
+    /// Retries every node running the given operation type according to
+    /// `retry_policy`, unless the node has its own policy
+    pub fn set_operation_retry_policy(&mut self, operation_type: OperationType, retry_policy: RetryPolicy) {
+        self.operation_retry_policies.insert(operation_type, retry_policy);
+    }
+
+    /// Runs at most `concurrency` operations at once, the others waiting for a
+    /// slot once their inputs are ready
+    pub fn set_concurrency(&mut self, concurrency: NonZeroUsize) {
+        self.concurrency = Some(concurrency);
+    }
+
+    /// Chooses which waiting node gets the next free slot when the concurrency
+    /// is limited, `Fifo` by default
+    pub fn set_scheduling_policy(&mut self, scheduling_policy: SchedulingPolicy) {
+        self.scheduling_policy = scheduling_policy;
+    }
+
+    /// Records node counts and durations in `metrics`, which may be shared
+    /// with other computations and exported while this one runs
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Adds a hook around the operation of every node, wrapped by the hooks
+    /// added before it
+    pub fn add_hook<H: NodeHook<T> + 'static>(&mut self, hook: H) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    /// Disrupts the attempts of nodes chosen by `injector`, to test how
+    /// failures, hangs, and delays are handled
+    pub fn set_fault_injector(&mut self, injector: FaultInjector) {
+        self.faults = Some(injector);
+    }
+
+    /// Aborts the computation once no node has completed for `idle`, for
+    /// example because a node hangs without a timeout, reporting which nodes
+    /// were running and which parents the others were waiting on in
+    /// `Report::stall`.  Must be longer than the slowest operation.
+    pub fn set_watchdog(&mut self, idle: Duration) {
+        self.watchdog = Some(idle);
+    }
+
+    /// Pauses the computation as set in `debugger`, sending every pause to the
+    /// returned channel, which closes once the computation is done
+    pub fn set_debugger(&mut self, debugger: Arc<Debugger<T>>) -> mpsc::UnboundedReceiver<Pause<T>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.debugger = Some(DebugSession::new(debugger, sender));
+        receiver
+    }
+
+    /// Records the inputs and output of every node, so that `Report::lineage`
+    /// tells how any value was derived
+    pub fn record_lineage(&mut self) {
+        self.lineage = true;
+    }
+
+    /// Runs every node in its own task, instead of running chains of nodes
+    /// with a single parent and a single child one after the other in a
+    /// single task
+    pub fn disable_fusion(&mut self) {
+        self.fusion = false;
+    }
+
+    /// Calls `callback` whenever a node starts running or finishes
+    pub fn set_progress_callback(&mut self, callback: ProgressCallback) {
+        self.progress = Some(callback);
+    }
+
+    /// Receives an event whenever a node starts running or finishes, replacing
+    /// any progress callback
+    pub fn progress_events(&mut self) -> mpsc::UnboundedReceiver<ProgressEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.progress = Some(Box::new(move |event| {
+            // Nobody may be listening anymore
+            let _ = sender.send(event.clone());
+        }));
+        receiver
+    }
+
+    /// Stops the whole computation once `deadline` has elapsed since the start
+    /// of `process`.  Nodes still running at that point fail, and nodes still
+    /// waiting for inputs are skipped.
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Estimated time from the start of every remaining node to the end of the
+    /// computation
+    fn priorities(&self, costs: &CostModel) -> HashMap<NodeId, Duration> {
+        let operation_types = self.computations.iter()
+            .map(|(id, computation)| (*id, computation.operation.operation_type))
+            .collect();
+        remaining_paths(&self.children, &operation_types, costs)
+    }
+
+    /// Parents of every remaining node, leaving out pruned nodes
+    fn parents(&self) -> HashMap<NodeId, Vec<NodeId>> {
+        let mut parents: HashMap<NodeId, Vec<NodeId>> = self.computations.keys().map(|id| (*id, vec![])).collect();
+        self.computations.keys().for_each(|id| {
+            self.children[id].iter().for_each(|child_id| {
+                if let Some(child_parents) = parents.get_mut(child_id) {
+                    child_parents.push(*id);
+                }
+            });
+        });
+        parents
+    }
+
+    /// Runs every node, in a `computation` span
+    pub async fn process(self, initial: T) -> Report<T> {
+        let span = info_span!("computation", nodes = self.computations.len(), duration = field::Empty);
+        self.run(initial).instrument(span).await
+    }
+
+    async fn run(self, initial: T) -> Report<T> {
+        let start = Instant::now();
+        let priorities = match &self.scheduling_policy {
+            SchedulingPolicy::Fifo => HashMap::new(),
+            SchedulingPolicy::CriticalPath(costs) => self.priorities(costs),
+        };
+        let parents = self.parents();
+        let chains = if self.fusion {
+            fusion::chains(&parents)
+        } else {
+            parents.keys().map(|id| vec![*id]).collect()
+        };
+        let scheduler = self.concurrency.map(|concurrency| Scheduler::new(concurrency, &parents));
+        let watchdog = self.watchdog.map(|idle| Watchdog::new(idle, parents));
+        let mut results = BTreeMap::new();
+        let deadline = self.deadline.map(|deadline| Instant::now() + deadline);
+        let token = self.token;
+        let abort = CancellationToken::new();
+        // Stops the node tasks without cancelling the token, which may be shared
+        let guard = CancelOnDrop::new(abort.clone());
+        let policy = self.policy;
+        let cache = self.cache;
+        let recorder = self.recorder;
+        let metrics = self.metrics;
+        let total = self.computations.len();
+        let progress = self.progress.map(|callback| ProgressTracker::new(callback, total));
+        let context = Arc::new(RunContext {
+            deadline, token: token.clone(), abort, policy, cache, recorder, scheduler, priorities, metrics,
+            progress, hooks: self.hooks, faults: self.faults, watchdog,
+            debugger: self.debugger, lineage: self.lineage.then(LineageRecorder::new),
+        });
+
+        debug!("connecting senders and receivers");
+        let mut computations = self.computations;
+        // Every node of a chain but the first takes its input straight from the
+        // previous one, without any channel
+        chains.iter().for_each(|chain| {
+            chain[..chain.len() - 1].iter().for_each(|id| computations.get_mut(id).unwrap().fuse());
+        });
+        let fed: HashSet<NodeId> = chains.iter().flat_map(|chain| chain[1..].iter().copied()).collect();
+        // Connect in id order, so that every node receives its inputs ordered by
+        // parent id, and results are ordered by sink id
+        let mut ids: Vec<NodeId> = computations.keys().copied().collect();
+        ids.sort_unstable();
         let mut result_receivers = vec![];
-        dag.nodes.iter().for_each(|(id, node)| {
+        let all_children = &self.children;
+        ids.iter().for_each(|id| {
             let mut parent = computations.remove(id).unwrap();
-            if node.children.is_empty() {
+            let children = &all_children[id];
+            if children.is_empty() {
                 // Nodes with no children mean a final result, so listen from the top
                 let (sender, receiver) = channel();
                 parent.add_output(sender);
-                result_receivers.push(receiver);
+                result_receivers.push((*id, receiver));
             } else {
-                // Send this node's result to all children
-                node.children.iter().for_each(|child_id| {
-                    let child = computations.get_mut(child_id).unwrap();
-                    let (sender, receiver) = channel();
-                    parent.add_output(sender);
-                    child.add_input(receiver);
+                // Send this node's result to all children still to run
+                children.iter().filter(|child_id| !fed.contains(child_id)).for_each(|child_id| {
+                    if let Some(child) = computations.get_mut(child_id) {
+                        let (sender, receiver) = channel();
+                        parent.add_output(sender);
+                        child.add_input(receiver);
+                    }
                 });
             }
-            computations.insert(id.clone(), parent);
+            computations.insert(*id, parent);
         });
-
-        if debug {
-            println!("Getting start nodes");
-        }
-        let mut initial_senders = vec![];
-        dag.starts.iter().for_each(|id| {
-            let computation = computations.get_mut(id).unwrap();
-            let (sender, receiver) = channel();
-            computation.add_input(receiver);
-            initial_senders.push(sender);
+        let mut inputs = self.inputs;
+        let initial = Arc::new(initial);
+        self.starts.iter().for_each(|id| {
+            if let Some(computation) = computations.get_mut(id) {
+                let (sender, receiver) = channel();
+                computation.add_input(receiver);
+                let _ = sender.send(inputs.remove(id).map(Arc::new).unwrap_or_else(|| initial.clone()));
+            }
         });
 
-        Self { result_receivers, initial_senders, computations, debug }
-    }
-
-    pub async fn process(mut self, initial: T) -> Vec<T> {
-        let mut results = vec![];
-
-        if self.debug {
-            println!("Creating tasks for node computation");
-        }
-        self.initial_senders.into_iter().for_each(|sender| { sender.send(initial.clone()).expect("Error sending"); });
-        let tasks = self.computations.into_iter()
-            .map(|(_, computation)| tokio::spawn(async move { computation.process().await }));
+        debug!("creating tasks for node computation");
+        let mut retry_policies = self.retry_policies;
+        let operation_retry_policies = self.operation_retry_policies;
+        debug!(tasks = chains.len(), "fused chains of nodes");
+        let tasks = chains.into_iter()
+            .map(|chain| {
+                let nodes: Vec<_> = chain.into_iter()
+                    .map(|id| {
+                        let mut computation = computations.remove(&id).unwrap();
+                        let retry_policy = retry_policies.remove(&id)
+                            .or_else(|| operation_retry_policies.get(&computation.operation.operation_type).cloned());
+                        if let Some(retry_policy) = retry_policy {
+                            computation.set_retry_policy(retry_policy);
+                        }
+                        computation
+                    })
+                    .collect();
+                let context = context.clone();
+                tokio::spawn(async move {
+                    // The nodes run one after the other, each handing its
+                    // result to the next
+                    let mut outcomes = vec![];
+                    let mut source = Source::Channels;
+                    for node in nodes {
+                        let id = node.id;
+                        let mut outcome = node.process(context.clone(), source).await;
+                        source = Source::Chain(outcome.handoff.take());
+                        outcomes.push((id, outcome));
+                    }
+                    outcomes
+                // Every node of the chain gets its own span, child of the computation span
+                }.instrument(Span::current()))
+            });
 
-        if self.debug {
-            println!("Starting everything!");
-        }
-        join_all(tasks).await;
+        debug!("starting everything");
+        let mut statuses: HashMap<NodeId, NodeStatus> = self.pruned.into_iter()
+            .map(|id| (id, NodeStatus::Pruned))
+            .collect();
+        let mut attempts = HashMap::new();
+        let mut values = HashMap::new();
+        let all = join_all(tasks);
+        tokio::pin!(all);
+        let stalled = async {
+            match &context.watchdog {
+                Some(watchdog) => watchdog.stalled().await,
+                None => pending().await,
+            }
+        };
+        let mut stall = None;
+        let outcomes = tokio::select! {
+            outcomes = &mut all => outcomes,
+            detected = stalled => {
+                error!(stall = %detected, "aborting stalled computation");
+                context.abort.cancel();
+                stall = Some(detected);
+                all.await
+            },
+        };
+        outcomes.into_iter().flat_map(|outcomes| outcomes.expect("Node task was cancelled")).for_each(|(id, outcome)| {
+            statuses.insert(id, outcome.status);
+            attempts.insert(id, outcome.attempts);
+            if let Some(value) = outcome.value {
+                values.insert(id, value);
+            }
+        });
 
-        if self.debug {
-            println!("Collecting results");
-        }
-        for receiver in &mut self.result_receivers {
-            let value = receiver.await.unwrap();
-            results.push(value);
+        debug!("collecting results");
+        match &self.targets {
+            Some(targets) => {
+                results.extend(targets.iter().filter_map(|id| Some((*id, values.get(id)?.clone()))));
+            },
+            None => {
+                for (id, receiver) in &mut result_receivers {
+                    if let Ok(value) = receiver.await {
+                        results.insert(*id, value);
+                    }
+                }
+            },
         }
 
-        results
+        guard.disarm();
+        let cancelled = token.is_cancelled();
+        let children = self.children;
+        let cache = context.cache.as_ref().map(|cache| cache.stats()).unwrap_or_default();
+        let lineage = context.lineage.as_ref().map(|lineage| lineage.take()).unwrap_or_default();
+        // Every node is done, so values are only still shared if retained as
+        // well as being results
+        let results = results.into_iter().map(|(id, value)| (id, Arc::unwrap_or_clone(value))).collect();
+        let values = values.into_iter().map(|(id, value)| (id, Arc::unwrap_or_clone(value))).collect();
+        let mut report = Report { results, statuses, attempts, cancelled, stall, cache, values, children, lineage };
+        report.skip_failed_descendants();
+        Span::current().record("duration", field::debug(start.elapsed()));
+        report
     }
 }
 
@@ -137,7 +951,14 @@ mod test {
 
     use super::*;
 
-    use crate::operation::OperationType;
+    use std::num::Wrapping;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use crate::cache::{CacheStats, MemoryCache};
+    use crate::random::DagDistribution;
+    use crate::retry::Backoff;
 
     async fn get_value() -> i32 {
         13
@@ -168,58 +989,56 @@ mod test {
     pub async fn process_default_computation() {
         let mut dag: Dag = Default::default();
         let operation: Operation = Default::default();
-        dag.add_node(operation.clone(), vec![]);
-        let computation = Computation::new(&dag, false);
-        let results = computation.process(0).await;
-        assert_eq!(results, vec![0]);
+        let id = dag.add_node(operation.clone(), vec![]);
+        let computation = Computation::new(&dag);
+        let results = computation.process(0).await.results;
+        assert_eq!(results, BTreeMap::from([(id, 0)]));
     }
 
     #[tokio::test]
     pub async fn process_default_many_branches() {
         let mut dag: Dag = Default::default();
         let operation:  Operation = Default::default();
-        dag.add_node(operation.clone(), vec![]);
-        dag.add_node(operation.clone(), vec![]);
-        dag.add_node(operation.clone(), vec![]);
-        let computation = Computation::new(&dag, false);
-        let results = computation.process(3).await;
-        assert_eq!(results, vec![0, 0, 0]);
+        let ids: Vec<NodeId> = (0..3).map(|_| dag.add_node(operation.clone(), vec![])).collect();
+        let computation = Computation::new(&dag);
+        let results = computation.process(3).await.results;
+        assert_eq!(results, ids.into_iter().map(|id| (id, 0)).collect());
     }
 
     #[tokio::test]
     pub async fn process_addition_single_result() {
         let mut dag: Dag = Default::default();
         let operation_type = OperationType::Sum;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let id1 = dag.add_node(operation.clone(), vec![]);
         let id2 = dag.add_node(operation.clone(), vec![]);
         let id3 = dag.add_node(operation.clone(), vec![]);
-        dag.add_node(operation.clone(), vec![id1, id2, id3]);
-        let computation = Computation::new(&dag, false);
-        let results = computation.process(3).await;
-        assert_eq!(results, vec![9]);
+        let id4 = dag.add_node(operation.clone(), vec![id1, id2, id3]);
+        let computation = Computation::new(&dag);
+        let results = computation.process(3).await.results;
+        assert_eq!(results, BTreeMap::from([(id4, 9)]));
     }
 
     #[tokio::test]
     pub async fn process_addition_dag() {
         let mut dag: Dag = Default::default();
         let operation_type = OperationType::Sum;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let id1 = dag.add_node(operation.clone(), vec![]);
         let id2 = dag.add_node(operation.clone(), vec![]);
         let id3 = dag.add_node(operation.clone(), vec![]);
         let id4 = dag.add_node(operation.clone(), vec![id1, id2]);
         let id5 = dag.add_node(operation.clone(), vec![id2, id3]);
-        dag.add_node(operation.clone(), vec![id4, id5]);
-        let computation = Computation::new(&dag, false);
-        let results = computation.process(1).await;
-        assert_eq!(results, vec![4]);
+        let id6 = dag.add_node(operation.clone(), vec![id4, id5]);
+        let computation = Computation::new(&dag);
+        let results = computation.process(1).await.results;
+        assert_eq!(results, BTreeMap::from([(id6, 4)]));
     }
 
     #[tokio::test(core_threads = 8)]
     pub async fn process_random_dag() {
         let dag: Dag = rand::random();
-        let computation = Computation::new(&dag, false);
+        let computation = Computation::new(&dag);
         computation.process(3).await;
     }
 
@@ -227,25 +1046,445 @@ mod test {
     pub async fn process_long_dag() {
         let mut dag: Dag = Default::default();
         let operation_type = OperationType::Sum;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let mut id = dag.add_node(operation.clone(), vec![]);
         for _ in 0..100_000 {
             id = dag.add_node(operation.clone(), vec![id]);
         }
-        let computation = Computation::new(&dag, false);
+        let computation = Computation::new(&dag);
         computation.process(3).await;
     }
 
+    #[tokio::test]
+    pub async fn fused_chain_keeps_every_node() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let mut ids = vec![dag.add_node(operation.clone(), vec![])];
+        for _ in 0..4 {
+            ids.push(dag.add_node(operation.clone(), vec![*ids.last().unwrap()]));
+        }
+        let mut injector = FaultInjector::new();
+        injector.inject_attempts(ids[1], Fault::Fail, 1);
+        injector.inject(ids[3], Fault::Fail);
+        let mut computation = Computation::new(&dag);
+        computation.set_fault_injector(injector);
+        computation.set_retry_policy(ids[1], RetryPolicy::new(2, Backoff::Fixed(Duration::from_millis(1))));
+        computation.retain_values();
+        let mut events = computation.progress_events();
+        let report = computation.process(3u64).await;
+
+        let statuses: Vec<&NodeStatus> = ids.iter().map(|id| &report.statuses[id]).collect();
+        let failed = NodeStatus::Failed(NodeError::Injected { id: ids[3], operation_type: OperationType::Sum });
+        assert_eq!(statuses, vec![&NodeStatus::Completed, &NodeStatus::Completed, &NodeStatus::Completed, &failed, &NodeStatus::Skipped]);
+        assert_eq!(report.attempts[&ids[1]].len(), 2);
+        assert_eq!(report.values.len(), 3);
+        let mut started = vec![];
+        while let Some(event) = events.recv().await {
+            if let ProgressEvent::Started { id } = event {
+                started.push(id);
+            }
+        }
+        assert_eq!(started, ids[..4]);
+    }
+
+    #[tokio::test]
+    pub async fn fused_chain_keeps_slot_while_first() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let mut chain = vec![dag.add_node(operation.clone(), vec![])];
+        for _ in 0..3 {
+            chain.push(dag.add_node(operation.clone(), vec![*chain.last().unwrap()]));
+        }
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let mut costs = CostModel::new();
+        costs.set_operation_cost(OperationType::Sum, Duration::from_millis(1));
+        let mut computation = Computation::new(&dag);
+        computation.set_concurrency(NonZeroUsize::new(1).unwrap());
+        computation.set_scheduling_policy(SchedulingPolicy::CriticalPath(costs));
+        let mut events = computation.progress_events();
+        assert!(computation.process(1u64).await.is_success());
+
+        let mut started = vec![];
+        while let Some(event) = events.recv().await {
+            if let ProgressEvent::Started { id } = event {
+                started.push(id);
+            }
+        }
+        // The last node of the chain is no more urgent than the nodes already
+        // waiting, so it gives up the slot
+        assert_eq!(started, vec![chain[0], chain[1], chain[2], id1, id2, chain[3]]);
+    }
+
     #[tokio::test(core_threads = 8)]
     pub async fn process_wide_dag() {
         let mut dag: Dag = Default::default();
         let operation_type = OperationType::Sum;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let ids = (0..100_000).map(|_| dag.add_node(operation.clone(), vec![])).collect();
-        dag.add_node(operation.clone(), ids);
-        let computation = Computation::new(&dag, false);
+        let id = dag.add_node(operation.clone(), ids);
+        let computation = Computation::new(&dag);
         let initial: u128 = 1;
-        let results = computation.process(initial).await;
-        assert_eq!(results, vec![100_000]);
+        let results = computation.process(initial).await.results;
+        assert_eq!(results, BTreeMap::from([(id, 100_000)]));
+    }
+
+    #[tokio::test]
+    pub async fn process_node_timeout() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Delay);
+        operation.timeout = Some(Duration::from_millis(50));
+        let id1 = dag.add_node(operation, vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let computation = Computation::new(&dag);
+        let report = computation.process(2).await;
+        // The failed sink leaves no gap, results are by node id
+        assert_eq!(report.results, BTreeMap::from([(id3, 2)]));
+        assert_eq!(report.errors(), vec![&NodeError::TimedOut {
+            id: id1, operation_type: OperationType::Delay, timeout: Duration::from_millis(50),
+        }]);
+        assert_eq!(report.skipped(), vec![id2]);
+        assert_eq!(report.statuses[&id3], NodeStatus::Completed);
+        assert!(!report.is_success());
+    }
+
+    #[tokio::test]
+    pub async fn process_deadline() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Delay), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Delay), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id2]);
+        let mut computation = Computation::new(&dag);
+        computation.set_deadline(Duration::from_millis(50));
+        let start = Instant::now();
+        let report = computation.process(2).await;
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(report.results.is_empty());
+        assert_eq!(report.errors(), vec![&NodeError::DeadlineExceeded { id: id1, operation_type: OperationType::Delay }]);
+        assert_eq!(report.skipped(), vec![id2, id3]);
+    }
+
+    #[tokio::test]
+    pub async fn process_retries() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Delay);
+        operation.timeout = Some(Duration::from_millis(10));
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let mut computation = Computation::new(&dag);
+        let backoff = Backoff::Exponential { initial: Duration::from_millis(1), max: Duration::from_millis(5) };
+        computation.set_operation_retry_policy(OperationType::Delay, RetryPolicy::new(3, backoff.clone()));
+        computation.set_retry_policy(id2, RetryPolicy::new(2, backoff));
+        let report = computation.process(2).await;
+        assert_eq!(report.attempts[&id1].len(), 3);
+        assert_eq!(report.attempts[&id2].len(), 2);
+        assert_eq!(report.attempts[&id3].len(), 1);
+        assert!(report.attempts[&id1].iter().all(|attempt| attempt.error.is_some()));
+        assert_eq!(report.attempts[&id3][0].error, None);
+        assert_eq!(report.errors().len(), 2);
+    }
+
+    #[tokio::test]
+    pub async fn process_retry_on() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Delay);
+        operation.timeout = Some(Duration::from_millis(10));
+        let id = dag.add_node(operation, vec![]);
+        let mut computation = Computation::new(&dag);
+        let mut retry_policy = RetryPolicy::new(5, Backoff::Fixed(Duration::from_millis(1)));
+        retry_policy.retry_on = Some(std::sync::Arc::new(|error| matches!(error, NodeError::Panicked { .. })));
+        computation.set_retry_policy(id, retry_policy);
+        let report = computation.process(2).await;
+        assert_eq!(report.attempts[&id].len(), 1);
+    }
+
+    #[tokio::test]
+    pub async fn process_cancelled() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Delay), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id2]);
+        let id4 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let computation = Computation::new(&dag);
+        let token = computation.cancellation_token();
+        tokio::spawn(async move {
+            delay_for(Duration::from_millis(50)).await;
+            token.cancel();
+        });
+        let start = Instant::now();
+        let report = computation.process(2).await;
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(report.cancelled);
+        assert_eq!(report.results, BTreeMap::from([(id4, 2)]));
+        assert_eq!(report.completed(), vec![id1, id4]);
+        assert_eq!(report.statuses[&id2], NodeStatus::Cancelled);
+        assert_eq!(report.statuses[&id3], NodeStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    pub async fn process_dropped() {
+        let mut dag: Dag = Default::default();
+        let id = dag.add_node(Operation::new(OperationType::Delay), vec![]);
+        let mut computation: Computation<u32> = Computation::new(&dag);
+        let token = computation.cancellation_token();
+        let mut events = computation.progress_events();
+        let result = timeout(Duration::from_millis(50), computation.process(2)).await;
+        assert!(result.is_err());
+        assert!(!token.is_cancelled());
+        // The node task stops long before the end of the delay
+        let finished = timeout(Duration::from_secs(1), async {
+            loop {
+                if let Some(ProgressEvent::Finished { id, status, .. }) = events.recv().await {
+                    return (id, status);
+                }
+            }
+        }).await.unwrap();
+        assert_eq!(finished, (id, NodeStatus::Cancelled));
+    }
+
+    #[tokio::test]
+    pub async fn shared_token_outlives_dropped_computation() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Delay), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let token = CancellationToken::new();
+        let mut dropped: Computation<u32> = Computation::new(&dag);
+        dropped.set_cancellation_token(token.clone());
+        let mut kept: Computation<u32> = Computation::new(&dag);
+        kept.set_cancellation_token(token.clone());
+        let kept = tokio::spawn(kept.process(2));
+        let result = timeout(Duration::from_millis(50), dropped.process(2)).await;
+        assert!(result.is_err());
+        assert!(!token.is_cancelled());
+        let report = kept.await.unwrap();
+        assert!(!report.cancelled);
+        assert_eq!(report.results, BTreeMap::from([(id2, 0)]));
+    }
+
+    #[tokio::test]
+    pub async fn process_fail_fast() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Delay);
+        operation.timeout = Some(Duration::from_millis(10));
+        let id1 = dag.add_node(operation, vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Delay), vec![]);
+        let id4 = dag.add_node(Operation::new(OperationType::Sum), vec![id2, id3]);
+        let mut computation: Computation<u32> = Computation::new(&dag);
+        computation.set_execution_policy(ExecutionPolicy::FailFast);
+        let start = Instant::now();
+        let report = computation.process(2).await;
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(!report.cancelled);
+        assert_eq!(report.failed(), vec![id1]);
+        assert_eq!(report.skipped(), vec![id2, id4]);
+        assert_eq!(report.skipped_descendants(id1), vec![id2, id4]);
+        assert_eq!(report.statuses[&id3], NodeStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    pub async fn process_continue_on_error() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Delay);
+        operation.timeout = Some(Duration::from_millis(10));
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id2]);
+        let id4 = dag.add_node(operation, vec![]);
+        let id5 = dag.add_node(Operation::new(OperationType::Sum), vec![id4]);
+        let id6 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id7 = dag.add_node(Operation::new(OperationType::Sum), vec![id6]);
+        let mut computation: Computation<u32> = Computation::new(&dag);
+        computation.set_execution_policy(ExecutionPolicy::ContinueOnError);
+        let report = computation.process(2).await;
+        assert_eq!(report.results, BTreeMap::from([(id7, 2)]));
+        assert_eq!(report.completed(), vec![id6, id7]);
+        assert_eq!(report.failed(), vec![id1, id4]);
+        assert_eq!(report.skipped(), vec![id2, id3, id5]);
+        assert_eq!(report.skipped_descendants(id1), vec![id2, id3]);
+        assert_eq!(report.skipped_descendants(id4), vec![id5]);
+    }
+
+    #[tokio::test]
+    pub async fn process_cached() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Product), vec![]);
+        let id3 = dag.add_node(operation.clone(), vec![id1, id2]);
+        let id4 = dag.add_node(operation.clone(), vec![id3]);
+        let cache = Arc::new(MemoryCache::new(100));
+
+        let mut computation = Computation::new(&dag);
+        computation.set_cache(cache.clone());
+        let report = computation.process(2).await;
+        assert_eq!(report.results, BTreeMap::from([(id4, 4)]));
+        assert_eq!(report.cache, CacheStats { hits: 0, misses: 4 });
+        assert_eq!(cache.len(), 4);
+
+        let mut computation = Computation::new(&dag);
+        computation.set_cache(cache.clone());
+        let report = computation.process(2).await;
+        assert_eq!(report.results, BTreeMap::from([(id4, 4)]));
+        assert_eq!(report.cache, CacheStats { hits: 4, misses: 0 });
+
+        // Only the new node needs to be computed
+        let id5 = dag.add_node(operation.clone(), vec![id1, id3]);
+        let mut computation = Computation::new(&dag);
+        computation.set_cache(cache.clone());
+        let report = computation.process(2).await;
+        assert_eq!(report.results, BTreeMap::from([(id4, 4), (id5, 6)]));
+        assert_eq!(report.cache, CacheStats { hits: 4, misses: 1 });
+        assert_eq!(report.statuses[&id5], NodeStatus::Completed);
+    }
+
+    #[tokio::test]
+    pub async fn process_targets() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let id3 = dag.add_node(operation.clone(), vec![id1, id2]);
+        let id4 = dag.add_node(Operation::new(OperationType::Delay), vec![id3]);
+        let id5 = dag.add_node(operation.clone(), vec![id2]);
+        let id6 = dag.add_node(Operation::new(OperationType::Delay), vec![]);
+        let mut computation = Computation::new(&dag);
+        computation.set_targets(vec![id5, id3]).unwrap();
+        let start = Instant::now();
+        let report = computation.process(2).await;
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(report.results, BTreeMap::from([(id3, 4), (id5, 2)]));
+        assert_eq!(report.completed(), vec![id1, id2, id3, id5]);
+        assert_eq!(report.pruned(), vec![id4, id6]);
+        assert!(report.is_success());
+    }
+
+    #[tokio::test]
+    pub async fn unknown_target_changes_nothing() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![id1]);
+        let id3 = dag.add_node(operation.clone(), vec![id1]);
+        let mut computation = Computation::new(&dag);
+        assert_eq!(computation.set_targets(vec![id2, 99]), Err(ComputationError::UnknownNode(99)));
+        let report = computation.process(2).await;
+        assert_eq!(report.completed(), vec![id1, id2, id3]);
+        assert!(report.pruned().is_empty());
+
+        let mut computation: Computation<u64> = Computation::new(&dag);
+        computation.set_targets(vec![id2]).unwrap();
+        assert_eq!(computation.set_targets(vec![id3]), Err(ComputationError::PrunedNode(id3)));
+    }
+
+    #[tokio::test]
+    pub async fn invalid_input_or_result_changes_nothing() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![id1]);
+        let id3 = dag.add_node(operation.clone(), vec![id1]);
+        let mut computation = Computation::new(&dag);
+        computation.set_targets(vec![id2]).unwrap();
+        assert_eq!(computation.set_input(id2, 5), Err(ComputationError::NotStartNode(id2)));
+        assert_eq!(computation.set_input(99, 5), Err(ComputationError::UnknownNode(99)));
+        assert_eq!(computation.set_result(id3, 5), Err(ComputationError::PrunedNode(id3)));
+        assert_eq!(computation.set_result(99, 5), Err(ComputationError::UnknownNode(99)));
+        let report = computation.process(2).await;
+        assert_eq!(report.results, BTreeMap::from([(id2, 2)]));
+    }
+
+    #[tokio::test]
+    pub async fn process_watched() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let id3 = dag.add_node(operation.clone(), vec![id1, id2]);
+        let id4 = dag.add_node(Operation::new(OperationType::Product), vec![id3]);
+        let mut computation = Computation::new(&dag);
+        computation.watch(&[id3, id1]).unwrap();
+        let report = computation.process(2).await;
+        assert_eq!(report.results, BTreeMap::from([(id4, 4)]));
+        let expected: HashMap<NodeId, u64> = vec![(id1, 2), (id3, 4)].into_iter().collect();
+        assert_eq!(report.values, expected);
+
+        let mut computation = Computation::new(&dag);
+        computation.retain_values();
+        let report = computation.process(2).await;
+        assert_eq!(report.values.len(), 4);
+        assert_eq!(report.values[&id4], 4);
+    }
+
+    #[tokio::test]
+    pub async fn watch_pruned_node() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![id1]);
+        let id3 = dag.add_node(operation.clone(), vec![id1]);
+        let mut computation = Computation::new(&dag);
+        computation.set_targets(vec![id2]).unwrap();
+        assert_eq!(computation.watch(&[id1, id3]), Err(ComputationError::PrunedNode(id3)));
+        assert_eq!(computation.watch(&[99]), Err(ComputationError::UnknownNode(99)));
+        let report = computation.process(2u64).await;
+        // Nothing was watched, not even the node that could have been
+        assert_eq!(report.values.keys().collect::<Vec<_>>(), vec![&id2]);
+    }
+
+    #[tokio::test]
+    pub async fn fan_out_shares_values() {
+        /// Keeps the inputs every node receives
+        struct Inputs(Arc<std::sync::Mutex<HashMap<NodeId, Vec<Arc<u64>>>>>);
+
+        impl NodeHook<u64> for Inputs {
+            fn before(&self, id: NodeId, _operation: &Operation, inputs: &mut Vec<Arc<u64>>) -> Result<(), String> {
+                self.0.lock().unwrap().insert(id, inputs.clone());
+                Ok(())
+            }
+        }
+
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let children: Vec<NodeId> = (0..3).map(|_| dag.add_node(Operation::new(OperationType::Sum), vec![id1])).collect();
+        let inputs = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let mut computation = Computation::new(&dag);
+        computation.add_hook(Inputs(inputs.clone()));
+        let report = computation.process(5u64).await;
+        assert_eq!(report.results, children.iter().map(|id| (*id, 5)).collect());
+
+        let inputs = inputs.lock().unwrap();
+        let first = &inputs[&children[0]][0];
+        assert!(children.iter().all(|id| Arc::ptr_eq(&inputs[id][0], first)));
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn matches_sequential_evaluation() {
+        let mut rng = StdRng::seed_from_u64(37);
+        for _ in 0..200 {
+            let distribution = DagDistribution {
+                min_nodes: 1,
+                max_nodes: rng.gen_range(2, 60),
+                edge_percentage: rng.gen_range(1, 101),
+                default_operation: None,
+            };
+            let dag = rng.sample(distribution);
+            // Wrapping arithmetic, so that large products overflow the same way
+            let initial = Wrapping(rng.gen_range(0u64, 10));
+            let expected = dag.evaluate_sequential(initial);
+
+            let mut computation = Computation::new(&dag);
+            computation.retain_values();
+            let report = computation.process(initial).await;
+            assert!(report.is_success());
+            assert_eq!(report.values, expected, "Mismatch for DAG {}", dag.dot());
+            let expected: BTreeMap<NodeId, Wrapping<u64>> = dag.nodes.iter()
+                .filter(|(_, node)| node.children.is_empty())
+                .map(|(id, _)| (*id, expected[id]))
+                .collect();
+            assert_eq!(report.results, expected);
+        }
     }
 }
@@ -1,9 +1,15 @@
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::hash::Hasher;
 
-use crate::operation::Operation;
+use crate::hash::StableHasher;
+use crate::operation::{Operable, Operation};
 
 pub type NodeId = u64;
 
+#[derive(Debug)]
 pub struct Node {
     pub id: NodeId,
     pub children: Vec<NodeId>,
@@ -17,6 +23,29 @@ impl Node {
     }
 }
 
+/// Reasons why a change to a `Dag` is rejected, or why a `Dag` is invalid
+#[derive(Clone, Debug, PartialEq)]
+pub enum DagError {
+    UnknownNode(NodeId),
+    /// The edge would make the graph cyclic
+    Cycle { parent: NodeId, child: NodeId },
+    /// The start nodes are not exactly the nodes without parents
+    InvalidStarts,
+}
+
+impl fmt::Display for DagError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DagError::UnknownNode(id) => write!(f, "unknown node {}", id),
+            DagError::Cycle { parent, child } => write!(f, "edge {} -> {} creates a cycle", parent, child),
+            DagError::InvalidStarts => write!(f, "start nodes do not match the nodes without parents"),
+        }
+    }
+}
+
+impl Error for DagError {}
+
+#[derive(Debug)]
 pub struct Dag {
     pub nodes: HashMap<NodeId, Node>,
     pub starts: Vec<NodeId>,
@@ -29,24 +58,182 @@ impl Dag {
         self.current_id
     }
 
+    /// Adds a node taking the results of `parents` as inputs, or a start node
+    /// if there are none.  Panics if a parent is not in the `Dag`, see
+    /// `try_add_node` otherwise.
     pub fn add_node(&mut self, operation: Operation, parents: Vec<NodeId>) -> NodeId {
+        match self.try_add_node(operation, parents) {
+            Ok(id) => id,
+            Err(error) => panic!("Cannot add node: {}", error),
+        }
+    }
+
+    /// Adds a node like `add_node`, unless a parent is not in the `Dag`, in
+    /// which case nothing changes
+    pub fn try_add_node(&mut self, operation: Operation, parents: Vec<NodeId>) -> Result<NodeId, DagError> {
+        if let Some(parent_id) = parents.iter().find(|parent_id| !self.nodes.contains_key(parent_id)) {
+            return Err(DagError::UnknownNode(*parent_id));
+        }
         let id = self.next_id();
         let node = Node::new(id, operation);
         self.nodes.insert(id, node);
-        if parents.len() == 0 {
+        if parents.is_empty() {
             self.starts.push(id);
         } else {
             parents.iter().for_each(|parent_id| {
                 self.nodes.get_mut(parent_id).unwrap().children.push(id)
             });
         }
-        id
+        Ok(id)
+    }
+
+    /// Makes `child` take the result of `parent` as an additional input,
+    /// unless it would create a cycle
+    pub fn add_edge(&mut self, parent: NodeId, child: NodeId) -> Result<(), DagError> {
+        if !self.nodes.contains_key(&parent) {
+            return Err(DagError::UnknownNode(parent));
+        }
+        if !self.nodes.contains_key(&child) {
+            return Err(DagError::UnknownNode(child));
+        }
+        if parent == child || self.descendants(child)?.contains(&parent) {
+            return Err(DagError::Cycle { parent, child });
+        }
+        self.nodes.get_mut(&parent).unwrap().children.push(child);
+        self.starts.retain(|id| *id != child);
+        Ok(())
+    }
+
+    /// Removes the node along with all of its edges.  Children left without any
+    /// parent become start nodes.
+    pub fn remove_node(&mut self, id: NodeId) -> Option<Node> {
+        let node = self.nodes.remove(&id)?;
+        self.starts.retain(|start| *start != id);
+        self.nodes.values_mut().for_each(|other| other.children.retain(|child_id| *child_id != id));
+        let parents = self.parents();
+        let mut children = node.children.clone();
+        children.sort_unstable();
+        children.dedup();
+        children.into_iter().for_each(|child_id| {
+            if parents[&child_id].is_empty() {
+                self.starts.push(child_id);
+            }
+        });
+        Some(node)
+    }
+
+    /// Checks that every edge leads to an existing node, that the start nodes
+    /// are exactly the nodes without parents, and that there is no cycle
+    pub fn validate(&self) -> Result<(), DagError> {
+        let mut parent_counts: HashMap<NodeId, usize> = self.nodes.keys().map(|id| (*id, 0)).collect();
+        for (id, node) in &self.nodes {
+            for child_id in &node.children {
+                *parent_counts.get_mut(child_id).ok_or(DagError::UnknownNode(*child_id))? += 1;
+                if child_id == id {
+                    return Err(DagError::Cycle { parent: *id, child: *id });
+                }
+            }
+        }
+        let mut starts = self.starts.clone();
+        starts.sort_unstable();
+        let mut expected: Vec<NodeId> = parent_counts.iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(id, _)| *id)
+            .collect();
+        expected.sort_unstable();
+        if starts != expected {
+            return Err(DagError::InvalidStarts);
+        }
+        let order = self.topological_order();
+        if order.len() < self.nodes.len() {
+            let cyclic = self.nodes.keys().find(|id| !order.contains(id)).unwrap();
+            let parent = self.nodes.iter()
+                .find(|(id, node)| !order.contains(id) && node.children.contains(cyclic))
+                .map(|(id, _)| *id)
+                .unwrap();
+            return Err(DagError::Cycle { parent, child: *cyclic });
+        }
+        Ok(())
+    }
+
+    /// Every node after all of its parents, choosing the smallest id first
+    /// among nodes whose parents are all done.  Nodes on a cycle are left out.
+    pub fn topological_order(&self) -> Vec<NodeId> {
+        let children = self.nodes.iter().map(|(id, node)| (*id, node.children.clone())).collect();
+        topological_order(&children)
+    }
+
+    /// Parents of every node, each list sorted by id, which is also the order in
+    /// which a node receives its inputs
+    pub fn parents(&self) -> HashMap<NodeId, Vec<NodeId>> {
+        let mut parents: HashMap<NodeId, Vec<NodeId>> = self.nodes.keys().map(|id| (*id, vec![])).collect();
+        self.nodes.iter().for_each(|(id, node)| {
+            node.children.iter().for_each(|child_id| parents.get_mut(child_id).unwrap().push(*id));
+        });
+        parents.values_mut().for_each(|parents| parents.sort_unstable());
+        parents
+    }
+
+    /// All nodes reachable from the given node, excluding itself, failing if
+    /// the node or any node reached is unknown
+    pub fn descendants(&self, id: NodeId) -> Result<HashSet<NodeId>, DagError> {
+        let mut descendants = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = self.nodes.get(&id).ok_or(DagError::UnknownNode(id))?;
+            node.children.iter().for_each(|child_id| {
+                if descendants.insert(*child_id) {
+                    stack.push(*child_id);
+                }
+            });
+        }
+        Ok(descendants)
+    }
+
+    /// Hash of the structure and operations of the DAG, identical for two DAGs
+    /// computing the same results, on any platform and with any Rust release
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = StableHasher::new();
+        let mut ids: Vec<&NodeId> = self.nodes.keys().collect();
+        ids.sort_unstable();
+        ids.into_iter().for_each(|id| {
+            let node = &self.nodes[id];
+            let mut children = node.children.clone();
+            children.sort_unstable();
+            hasher.write_u64(*id);
+            hasher.write_field(format!("{:?}", node.operation.operation_type).as_bytes());
+            hasher.write_u64(children.len() as u64);
+            children.iter().for_each(|child_id| hasher.write_u64(*child_id));
+        });
+        let mut starts = self.starts.clone();
+        starts.sort_unstable();
+        hasher.write_u64(starts.len() as u64);
+        starts.iter().for_each(|id| hasher.write_u64(*id));
+        hasher.finish()
+    }
+
+    /// Values of every node computed one at a time in topological order, as a
+    /// simple reference for the asynchronous `Computation`
+    pub fn evaluate_sequential<T>(&self, initial: T) -> HashMap<NodeId, T>
+    where for<'a> T: Operable<'a, T> + 'static {
+        let parents = self.parents();
+        let mut values: HashMap<NodeId, T> = HashMap::new();
+        self.topological_order().into_iter().for_each(|id| {
+            let inputs: Vec<T> = if parents[&id].is_empty() {
+                vec![initial.clone()]
+            } else {
+                parents[&id].iter().map(|parent_id| values[parent_id].clone()).collect()
+            };
+            let value = self.nodes[&id].operation.evaluate(&inputs);
+            values.insert(id, value);
+        });
+        values
     }
 
     pub fn dot(&self) -> String {
         let mut dot = "digraph {\n".to_owned();
         self.nodes.iter().for_each(|(parent_id, node)| {
-            if node.children.len() == 0 {
+            if node.children.is_empty() {
                 dot += &format!("  {};\n", parent_id);
             } else {
                 node.children.iter().for_each(|child_id| {
@@ -60,6 +247,33 @@ impl Dag {
 
 }
 
+/// Topological order of the graph given by the children of every node
+pub(crate) fn topological_order(children: &HashMap<NodeId, Vec<NodeId>>) -> Vec<NodeId> {
+    let mut remaining: HashMap<NodeId, usize> = children.keys().map(|id| (*id, 0)).collect();
+    children.values().flatten().for_each(|child_id| {
+        if let Some(count) = remaining.get_mut(child_id) {
+            *count += 1;
+        }
+    });
+    let mut ready: BinaryHeap<Reverse<NodeId>> = remaining.iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(id, _)| Reverse(*id))
+        .collect();
+    let mut order = vec![];
+    while let Some(Reverse(id)) = ready.pop() {
+        order.push(id);
+        children[&id].iter().for_each(|child_id| {
+            if let Some(count) = remaining.get_mut(child_id) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(Reverse(*child_id));
+                }
+            }
+        });
+    }
+    order
+}
+
 impl Default for Dag {
     fn default() -> Self {
         let nodes = HashMap::new();
@@ -99,6 +313,125 @@ mod tests {
         assert_eq!(id, 4);
     }
 
+    #[test]
+    pub fn add_node_with_unknown_parent() {
+        let mut dag: Dag = Default::default();
+        let operation: Operation = Default::default();
+        let id = dag.add_node(operation.clone(), vec![]);
+        assert_eq!(dag.try_add_node(operation.clone(), vec![id, 7]), Err(DagError::UnknownNode(7)));
+        assert_eq!(dag.nodes.len(), 1);
+        assert!(dag.nodes[&id].children.is_empty());
+        assert_eq!(dag.try_add_node(operation.clone(), vec![id]), Ok(2));
+        assert_eq!(dag.validate(), Ok(()));
+    }
+
+    #[test]
+    pub fn descendants() {
+        let mut dag: Dag = Default::default();
+        let operation: Operation = Default::default();
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let id3 = dag.add_node(operation.clone(), vec![id1]);
+        let id4 = dag.add_node(operation.clone(), vec![id3, id2]);
+        assert_eq!(dag.descendants(id1), Ok(vec![id3, id4].into_iter().collect()));
+        assert_eq!(dag.parents()[&id4], vec![id2, id3]);
+        assert!(dag.parents()[&id1].is_empty());
+        assert_eq!(dag.descendants(id2), Ok(vec![id4].into_iter().collect()));
+        assert!(dag.descendants(id4).unwrap().is_empty());
+        assert_eq!(dag.descendants(99), Err(DagError::UnknownNode(99)));
+    }
+
+    #[test]
+    pub fn fingerprint() {
+        let mut dag: Dag = Default::default();
+        let operation: Operation = Default::default();
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let fingerprint = dag.fingerprint();
+        assert_eq!(fingerprint, dag.fingerprint());
+        dag.add_node(operation.clone(), vec![id1, id2]);
+        let fingerprint = dag.fingerprint();
+        assert_ne!(fingerprint, Dag::default().fingerprint());
+        dag.nodes.get_mut(&id1).unwrap().operation = Operation::new(crate::operation::OperationType::Sum);
+        assert_ne!(fingerprint, dag.fingerprint());
+    }
+
+    #[test]
+    pub fn fingerprint_is_stable() {
+        // Checkpoints written by any earlier build must still be resumable
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(crate::operation::OperationType::Sum), vec![]);
+        dag.add_node(Operation::new(crate::operation::OperationType::Product), vec![id1]);
+        assert_eq!(dag.fingerprint(), 0x98f9_e1eb_bb54_ebab);
+    }
+
+    #[test]
+    pub fn evaluate_sequential() {
+        let mut dag: Dag = Default::default();
+        let sum = Operation::new(crate::operation::OperationType::Sum);
+        let product = Operation::new(crate::operation::OperationType::Product);
+        let id1 = dag.add_node(sum.clone(), vec![]);
+        let id2 = dag.add_node(sum.clone(), vec![]);
+        let id3 = dag.add_node(product.clone(), vec![id1, id2]);
+        let id4 = dag.add_node(sum.clone(), vec![id3, id1]);
+        let id5 = dag.add_node(Operation::new(crate::operation::OperationType::Delay), vec![id4]);
+        let values = dag.evaluate_sequential(3);
+        assert_eq!(values[&id3], 9);
+        assert_eq!(values[&id4], 12);
+        assert_eq!(values[&id5], 0);
+        assert_eq!(values.len(), 5);
+    }
+
+    #[test]
+    pub fn add_edges() {
+        let mut dag: Dag = Default::default();
+        let operation: Operation = Default::default();
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let id3 = dag.add_node(operation.clone(), vec![id1]);
+        assert_eq!(dag.add_edge(id3, id2), Ok(()));
+        assert_eq!(dag.starts, vec![id1]);
+        assert_eq!(dag.add_edge(id2, id1), Err(DagError::Cycle { parent: id2, child: id1 }));
+        assert_eq!(dag.add_edge(id2, id2), Err(DagError::Cycle { parent: id2, child: id2 }));
+        assert_eq!(dag.add_edge(id1, 9), Err(DagError::UnknownNode(9)));
+        assert_eq!(dag.topological_order(), vec![id1, id3, id2]);
+        assert_eq!(dag.validate(), Ok(()));
+        assert_eq!(dag.evaluate_sequential(4)[&id2], 0);
+    }
+
+    #[test]
+    pub fn remove_nodes() {
+        let mut dag: Dag = Default::default();
+        let operation: Operation = Default::default();
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let id3 = dag.add_node(operation.clone(), vec![id1, id2]);
+        let id4 = dag.add_node(operation.clone(), vec![id1]);
+        assert!(dag.remove_node(id1).is_some());
+        assert!(dag.remove_node(id1).is_none());
+        let mut starts = dag.starts.clone();
+        starts.sort_unstable();
+        assert_eq!(starts, vec![id2, id4]);
+        assert_eq!(dag.parents()[&id3], vec![id2]);
+        assert_eq!(dag.validate(), Ok(()));
+    }
+
+    #[test]
+    pub fn invalid_dags() {
+        let mut dag: Dag = Default::default();
+        let operation: Operation = Default::default();
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![id1]);
+        dag.starts.push(id2);
+        assert_eq!(dag.validate(), Err(DagError::InvalidStarts));
+        dag.starts.pop();
+        dag.nodes.get_mut(&id2).unwrap().children.push(id1);
+        dag.starts.clear();
+        assert!(matches!(dag.validate(), Err(DagError::Cycle { .. })));
+        dag.nodes.get_mut(&id2).unwrap().children = vec![7];
+        assert_eq!(dag.validate(), Err(DagError::UnknownNode(7)));
+    }
+
     #[test]
     pub fn dot_print() {
         let mut dag: Dag = Default::default();
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{MutexGuard, mpsc, oneshot};
+
+use crate::dag::NodeId;
+use crate::operation::OperationType;
+
+/// Inputs and output of a node that completed while debugging
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeValues<T> {
+    pub inputs: Vec<T>,
+    pub output: T,
+}
+
+enum Command<T> {
+    /// Run the node, then pause at the next node ready to run
+    Step(Option<T>),
+    /// Run until the next breakpoint
+    Continue(Option<T>),
+}
+
+/// Node stopped by the debugger once its inputs are ready, before running its
+/// operation.  Dropping it continues like `resume`.
+pub struct Pause<T> {
+    pub id: NodeId,
+    pub operation_type: OperationType,
+    pub inputs: Vec<T>,
+    value: Option<T>,
+    reply: oneshot::Sender<Command<T>>,
+}
+
+impl<T> Pause<T> {
+    /// Uses `value` as the result of the node instead of running its operation
+    pub fn set_value(&mut self, value: T) {
+        self.value = Some(value);
+    }
+
+    /// Runs the node, and pauses at the next node ready to run once it has
+    /// completed, so that nodes run one at a time
+    pub fn step(self) {
+        let _ = self.reply.send(Command::Step(self.value));
+    }
+
+    /// Runs every node until one matching a breakpoint is ready
+    pub fn resume(self) {
+        let _ = self.reply.send(Command::Continue(self.value));
+    }
+}
+
+struct DebugState {
+    breakpoints: HashSet<NodeId>,
+    operation_breakpoints: HashSet<OperationType>,
+    /// Whether to pause at every node, set after a step
+    stepping: bool,
+}
+
+/// Pauses a computation at breakpoints, set on node ids or operation types, to
+/// inspect values and step through nodes.  Shared between the computation,
+/// given it with `Computation::set_debugger`, and whoever handles the pauses.
+pub struct Debugger<T> {
+    state: Mutex<DebugState>,
+    /// Held by the node paused, and by the node being stepped until it completes
+    gate: tokio::sync::Mutex<()>,
+    values: Mutex<HashMap<NodeId, NodeValues<T>>>,
+}
+
+impl<T: Clone> Debugger<T> {
+    /// Creates a debugger pausing at the first node ready to run, or only at
+    /// breakpoints if `stepping` is false
+    pub fn new(stepping: bool) -> Self {
+        let breakpoints = HashSet::new();
+        let operation_breakpoints = HashSet::new();
+        let state = Mutex::new(DebugState { breakpoints, operation_breakpoints, stepping });
+        let gate = tokio::sync::Mutex::new(());
+        let values = Mutex::new(HashMap::new());
+        Self { state, gate, values }
+    }
+
+    pub fn break_at(&self, id: NodeId) {
+        self.state.lock().unwrap().breakpoints.insert(id);
+    }
+
+    pub fn break_on(&self, operation_type: OperationType) {
+        self.state.lock().unwrap().operation_breakpoints.insert(operation_type);
+    }
+
+    pub fn remove_breakpoint(&self, id: NodeId) {
+        self.state.lock().unwrap().breakpoints.remove(&id);
+    }
+
+    pub fn remove_operation_breakpoint(&self, operation_type: OperationType) {
+        self.state.lock().unwrap().operation_breakpoints.remove(&operation_type);
+    }
+
+    /// Inputs and output of the node, if it completed
+    pub fn inspect(&self, id: NodeId) -> Option<NodeValues<T>> {
+        self.values.lock().unwrap().get(&id).cloned()
+    }
+
+    fn should_pause(&self, id: NodeId, operation_type: OperationType) -> bool {
+        let state = self.state.lock().unwrap();
+        state.stepping || state.breakpoints.contains(&id) || state.operation_breakpoints.contains(&operation_type)
+    }
+}
+
+/// Debugger attached to a single run, sending its pauses to a channel
+pub(crate) struct DebugSession<T> {
+    debugger: Arc<Debugger<T>>,
+    pauses: mpsc::UnboundedSender<Pause<T>>,
+}
+
+impl<T: Clone> DebugSession<T> {
+    pub(crate) fn new(debugger: Arc<Debugger<T>>, pauses: mpsc::UnboundedSender<Pause<T>>) -> Self {
+        Self { debugger, pauses }
+    }
+
+    /// Waits for a command if the node should pause, returning the value to
+    /// use instead of running it, and a guard to hold until it completes
+    /// when stepping
+    pub(crate) async fn pause(&self, id: NodeId, operation_type: OperationType, inputs: &[Arc<T>])
+    -> (Option<T>, Option<MutexGuard<'_, ()>>) {
+        if !self.debugger.should_pause(id, operation_type) {
+            return (None, None);
+        }
+        let guard = self.debugger.gate.lock().await;
+        // The previous pause may have resumed everything while this node waited
+        if !self.debugger.should_pause(id, operation_type) {
+            return (None, None);
+        }
+        let (reply, command) = oneshot::channel();
+        let inputs = inputs.iter().map(|input| T::clone(input)).collect();
+        let pause = Pause { id, operation_type, inputs, value: None, reply };
+        if self.pauses.send(pause).is_err() {
+            // Nobody is handling pauses anymore
+            return (None, None);
+        }
+        let command = command.await.unwrap_or(Command::Continue(None));
+        let (value, stepping) = match command {
+            Command::Step(value) => (value, true),
+            Command::Continue(value) => (value, false),
+        };
+        self.debugger.state.lock().unwrap().stepping = stepping;
+        (value, if stepping { Some(guard) } else { None })
+    }
+
+    pub(crate) fn record(&self, id: NodeId, inputs: &[Arc<T>], output: &T) {
+        let inputs = inputs.iter().map(|input| T::clone(input)).collect();
+        let values = NodeValues { inputs, output: output.clone() };
+        self.debugger.values.lock().unwrap().insert(id, values);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::num::NonZeroUsize;
+
+    use crate::computation::Computation;
+    use crate::dag::Dag;
+    use crate::operation::Operation;
+
+    /// Product of a sum and of the initial value
+    fn dag() -> (Dag, Vec<NodeId>) {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Product), vec![id1, id2]);
+        (dag, vec![id1, id2, id3])
+    }
+
+    #[tokio::test]
+    pub async fn steps_through_every_node() {
+        let (dag, ids) = dag();
+        let debugger = Arc::new(Debugger::new(true));
+        let mut computation = Computation::new(&dag);
+        let mut pauses = computation.set_debugger(debugger.clone());
+        let run = tokio::spawn(computation.process(3u64));
+
+        let mut paused = vec![];
+        while let Some(pause) = pauses.recv().await {
+            // Stepping waits for the previous node to complete
+            if let Some(previous) = paused.last() {
+                assert!(debugger.inspect(*previous).is_some());
+            }
+            paused.push(pause.id);
+            pause.step();
+        }
+        assert_eq!(paused, ids);
+        assert_eq!(run.await.unwrap().results, BTreeMap::from([(ids[2], 9)]));
+        assert_eq!(debugger.inspect(ids[2]), Some(NodeValues { inputs: vec![3, 3], output: 9 }));
+    }
+
+    #[tokio::test]
+    pub async fn steps_with_limited_concurrency() {
+        let mut dag: Dag = Default::default();
+        let starts: Vec<NodeId> = (0..3).map(|_| dag.add_node(Operation::new(OperationType::Sum), vec![])).collect();
+        let sink = dag.add_node(Operation::new(OperationType::Sum), starts);
+        let debugger = Arc::new(Debugger::new(true));
+        let mut computation = Computation::new(&dag);
+        computation.set_concurrency(NonZeroUsize::new(1).unwrap());
+        let mut pauses = computation.set_debugger(debugger);
+        let run = tokio::spawn(computation.process(3u64));
+
+        let steps = async {
+            let mut paused = 0;
+            while let Some(pause) = pauses.recv().await {
+                paused += 1;
+                pause.step();
+            }
+            paused
+        };
+        let paused = tokio::time::timeout(std::time::Duration::from_secs(5), steps).await.expect("Stepping deadlocked");
+        assert_eq!(paused, 4);
+        assert_eq!(run.await.unwrap().results, BTreeMap::from([(sink, 9)]));
+    }
+
+    #[tokio::test]
+    pub async fn pauses_at_breakpoints() {
+        let (dag, ids) = dag();
+        let debugger = Arc::new(Debugger::new(false));
+        debugger.break_at(ids[1]);
+        debugger.break_on(OperationType::Product);
+        let mut computation = Computation::new(&dag);
+        let mut pauses = computation.set_debugger(debugger.clone());
+        let run = tokio::spawn(computation.process(3u64));
+
+        let mut paused = vec![];
+        while let Some(pause) = pauses.recv().await {
+            paused.push((pause.id, pause.inputs.clone()));
+            pause.resume();
+        }
+        assert_eq!(paused, vec![(ids[1], vec![3]), (ids[2], vec![3, 3])]);
+        assert!(run.await.unwrap().is_success());
+    }
+
+    #[tokio::test]
+    pub async fn overrides_paused_node() {
+        let (dag, ids) = dag();
+        let debugger = Arc::new(Debugger::new(false));
+        debugger.break_at(ids[1]);
+        let mut computation = Computation::new(&dag);
+        let mut pauses = computation.set_debugger(debugger.clone());
+        let run = tokio::spawn(computation.process(3u64));
+
+        let mut pause = pauses.recv().await.unwrap();
+        pause.set_value(10);
+        pause.resume();
+        assert!(pauses.recv().await.is_none());
+        assert_eq!(run.await.unwrap().results, BTreeMap::from([(ids[2], 30)]));
+        assert_eq!(debugger.inspect(ids[1]), Some(NodeValues { inputs: vec![3], output: 10 }));
+    }
+}
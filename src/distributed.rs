@@ -0,0 +1,1155 @@
+//! Execution of a `Dag` on worker processes.  Every worker keeps the values it
+//! computes until the end of the run, and fetches the inputs of its nodes
+//! straight from the workers holding them, so that only the initial value and
+//! the values of the sinks and watched nodes go through the `Coordinator`.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::{AbortHandle, FutureExt, abortable, pending};
+use futures::stream::{Stream, StreamExt};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, delay_until, timeout, timeout_at};
+use tracing::{Instrument, debug, debug_span, warn};
+
+use crate::cancel::CancellationToken;
+use crate::computation::{Sendable, panic_message};
+use crate::dag::{Dag, NodeId};
+use crate::error::{ComputationError, NodeError};
+use crate::operation::{Operable, Operation, OperationType};
+use crate::report::{NodeStatus, Report};
+use crate::retry::Attempt;
+
+/// Keeps apart the values of the runs of different coordinators on a worker
+type RunId = u64;
+
+/// Where a worker finds the inputs of the node it runs
+#[derive(Serialize, Deserialize)]
+struct Inputs<T> {
+    /// Only input of a start node
+    initial: Option<T>,
+    /// Parents whose values are the inputs, in order.  The worker holds them
+    /// itself, unless they are sent along or held by another worker.
+    parents: Vec<NodeId>,
+    /// Values of parents sent along by the coordinator
+    values: Vec<(NodeId, T)>,
+    /// Parents held by other workers, with their address
+    remote: Vec<(NodeId, Address)>,
+}
+
+/// Sent to a worker, one JSON object per line, by a coordinator or, to fetch
+/// an input, by another worker
+#[derive(Serialize, Deserialize)]
+enum Request<T> {
+    Run {
+        run: RunId,
+        id: NodeId,
+        operation_type: OperationType,
+        /// `Operation::timeout`, applied by the worker
+        timeout: Option<Duration>,
+        inputs: Inputs<T>,
+        /// Whether the value goes back to the coordinator, instead of staying
+        /// on the worker for the children
+        reply_value: bool,
+    },
+    Fetch { run: RunId, id: NodeId },
+}
+
+/// Sent back by a worker, one JSON object per line
+#[derive(Serialize, Deserialize)]
+enum Response<T> {
+    /// The value is only included when the request asked for it
+    Done { id: NodeId, value: Option<T> },
+    Failed { id: NodeId, message: String },
+    TimedOut { id: NodeId, timeout: Duration },
+    /// The worker could not get the value of this input
+    Unavailable { id: NodeId, input: NodeId },
+    /// Answer to `Request::Fetch`, without a value if the worker does not hold it
+    Value { id: NodeId, value: Option<T> },
+}
+
+/// Where a worker listens: `unix:<path>` for a Unix socket, or a TCP address
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Address {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for Address {
+    type Err = std::convert::Infallible;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        Ok(match address.strip_prefix("unix:") {
+            Some(path) => Address::Unix(PathBuf::from(path)),
+            None => Address::Tcp(address.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Tcp(address) => write!(f, "{}", address),
+            Address::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DistributedError {
+    Io(io::Error),
+    /// Every worker disconnected before the computation finished
+    NoWorkers,
+}
+
+impl fmt::Display for DistributedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistributedError::Io(error) => write!(f, "worker connection error: {}", error),
+            DistributedError::NoWorkers => write!(f, "no workers left to run the computation"),
+        }
+    }
+}
+
+impl Error for DistributedError {}
+
+impl From<io::Error> for DistributedError {
+    fn from(error: io::Error) -> Self {
+        DistributedError::Io(error)
+    }
+}
+
+fn invalid_data(error: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Serializes a message as a single line
+fn to_line<M: Serialize>(message: &M) -> io::Result<String> {
+    let mut line = serde_json::to_string(message).map_err(invalid_data)?;
+    line.push('\n');
+    Ok(line)
+}
+
+/// Values computed by a worker, shared by all of its connections
+type Store<T> = Arc<Mutex<HashMap<(RunId, NodeId), T>>>;
+
+/// Runs a worker at the given address until the token is cancelled, executing
+/// the nodes sent by any number of coordinators
+pub async fn serve<T>(address: &Address, token: CancellationToken) -> io::Result<()>
+where for<'a> T: Operable<'a, T> + Sendable + Serialize + DeserializeOwned + 'static {
+    match address {
+        Address::Tcp(address) => serve_tcp::<T>(TcpListener::bind(address).await?, token).await,
+        Address::Unix(path) => {
+            let mut listener = UnixListener::bind(path)?;
+            accept_connections::<T, _, _>(listener.incoming(), token).await
+        },
+    }
+}
+
+/// Runs a worker on an already bound TCP listener until the token is cancelled
+pub async fn serve_tcp<T>(mut listener: TcpListener, token: CancellationToken) -> io::Result<()>
+where for<'a> T: Operable<'a, T> + Sendable + Serialize + DeserializeOwned + 'static {
+    accept_connections::<T, _, _>(listener.incoming(), token).await
+}
+
+/// Handles every incoming connection of a listener until the token is cancelled
+async fn accept_connections<T, S, I>(mut incoming: I, token: CancellationToken) -> io::Result<()>
+where for<'a> T: Operable<'a, T> + Sendable + Serialize + DeserializeOwned + 'static,
+      S: AsyncRead + AsyncWrite + Send + 'static,
+      I: Stream<Item = io::Result<S>> + Unpin {
+    let store: Store<T> = Default::default();
+    loop {
+        tokio::select! {
+            accepted = incoming.next() => match accepted {
+                Some(stream) => spawn_worker_connection::<T, S>(stream?, store.clone()),
+                None => return Ok(()),
+            },
+            _ = token.cancelled() => return Ok(()),
+        }
+    }
+}
+
+fn spawn_worker_connection<T, S>(stream: S, store: Store<T>)
+where for<'a> T: Operable<'a, T> + Sendable + Serialize + DeserializeOwned + 'static,
+      S: AsyncRead + AsyncWrite + Send + 'static {
+    tokio::spawn(async move {
+        if let Err(error) = handle_worker_connection::<T, S>(stream, store).await {
+            warn!(%error, "worker connection closed");
+        }
+    });
+}
+
+/// Runs every requested node concurrently, sending back results as soon as
+/// they are ready, and answers fetches from other workers.  Once the
+/// connection closes, the nodes still running are abandoned and the values of
+/// the runs it started are dropped.
+async fn handle_worker_connection<T, S>(stream: S, store: Store<T>) -> io::Result<()>
+where for<'a> T: Operable<'a, T> + Sendable + Serialize + DeserializeOwned + 'static,
+      S: AsyncRead + AsyncWrite + Send + 'static {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Response<T>>();
+    let writing = tokio::spawn(async move {
+        while let Some(response) = receiver.recv().await {
+            writer.write_all(to_line(&response)?.as_bytes()).await?;
+        }
+        Ok::<(), io::Error>(())
+    });
+
+    let mut runs = HashSet::new();
+    let mut running: Vec<AbortHandle> = vec![];
+    let reading = async {
+        while let Some(line) = lines.next_line().await? {
+            match serde_json::from_str(&line).map_err(invalid_data)? {
+                Request::Run { run, id, operation_type, timeout, inputs, reply_value } => {
+                    runs.insert(run);
+                    let span = debug_span!("node", id, operation = ?operation_type);
+                    let store = store.clone();
+                    let sender = sender.clone();
+                    let (processing, abort) = abortable(async move {
+                        debug!("processing node");
+                        let response = run_node(&store, run, id, operation_type, timeout, inputs, reply_value).await;
+                        // The coordinator may be gone, nothing left to do then
+                        let _ = sender.send(response);
+                    }.instrument(span));
+                    running.push(abort);
+                    tokio::spawn(processing);
+                },
+                Request::Fetch { run, id } => {
+                    let value = store.lock().unwrap().get(&(run, id)).cloned();
+                    let _ = sender.send(Response::Value { id, value });
+                },
+            }
+        }
+        Ok::<(), io::Error>(())
+    };
+    let read = reading.await;
+    running.iter().for_each(AbortHandle::abort);
+    store.lock().unwrap().retain(|(run, _), _| !runs.contains(run));
+    drop(sender);
+    read?;
+    writing.await.expect("Writer task failed")
+}
+
+/// Gathers the inputs of a node, then runs it, keeping its value for the
+/// children unless the coordinator asked for it
+async fn run_node<T>(
+    store: &Store<T>,
+    run: RunId,
+    id: NodeId,
+    operation_type: OperationType,
+    limit: Option<Duration>,
+    inputs: Inputs<T>,
+    reply_value: bool,
+) -> Response<T>
+where for<'a> T: Operable<'a, T> + Sendable + Serialize + DeserializeOwned + 'static {
+    let values = match gather(store, run, inputs).await {
+        Ok(values) => values,
+        Err(input) => return Response::Unavailable { id, input },
+    };
+
+    let operation = Operation::new(operation_type);
+    let processing = AssertUnwindSafe(operation.process(&values)).catch_unwind();
+    let result = match limit {
+        Some(duration) => timeout(duration, processing).await.map_err(|_| duration),
+        None => Ok(processing.await),
+    };
+    match result {
+        Ok(Ok(value)) if reply_value => Response::Done { id, value: Some(value) },
+        Ok(Ok(value)) => {
+            store.lock().unwrap().insert((run, id), value);
+            Response::Done { id, value: None }
+        },
+        Ok(Err(panic)) => Response::Failed { id, message: panic_message(panic) },
+        Err(timeout) => Response::TimedOut { id, timeout },
+    }
+}
+
+/// Values of the inputs, in order, or the id of one that is not available
+async fn gather<T>(store: &Store<T>, run: RunId, inputs: Inputs<T>) -> Result<Vec<T>, NodeId>
+where T: Clone + Serialize + DeserializeOwned {
+    if let Some(initial) = inputs.initial {
+        return Ok(vec![initial]);
+    }
+    let mut found: HashMap<NodeId, T> = inputs.values.into_iter().collect();
+    for (input_id, worker) in inputs.remote {
+        match fetch(&worker, run, input_id).await {
+            Ok(Some(value)) => {
+                found.insert(input_id, value);
+            },
+            Ok(None) => return Err(input_id),
+            Err(error) => {
+                warn!(%error, input = input_id, %worker, "error fetching input");
+                return Err(input_id);
+            },
+        }
+    }
+    let stored = store.lock().unwrap();
+    inputs.parents.iter().map(|input_id| match found.get(input_id) {
+        Some(value) => Ok(value.clone()),
+        None => stored.get(&(run, *input_id)).cloned().ok_or(*input_id),
+    }).collect()
+}
+
+/// Asks the worker at the given address for the value of a node it computed
+async fn fetch<T>(worker: &Address, run: RunId, id: NodeId) -> io::Result<Option<T>>
+where T: Serialize + DeserializeOwned {
+    let request: Request<T> = Request::Fetch { run, id };
+    match worker {
+        Address::Tcp(address) => exchange(TcpStream::connect(address).await?, &request).await,
+        Address::Unix(path) => exchange(UnixStream::connect(path).await?, &request).await,
+    }
+}
+
+/// Sends a single fetch request, returning the value of the answer
+async fn exchange<T, S>(stream: S, request: &Request<T>) -> io::Result<Option<T>>
+where T: Serialize + DeserializeOwned, S: AsyncRead + AsyncWrite {
+    let (reader, mut writer) = tokio::io::split(stream);
+    writer.write_all(to_line(request)?.as_bytes()).await?;
+    let line = BufReader::new(reader).lines().next_line().await?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "worker closed the connection"))?;
+    match serde_json::from_str(&line).map_err(invalid_data)? {
+        Response::Value { value, .. } => Ok(value),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected answer to a fetch")),
+    }
+}
+
+/// Assigns every node to one of `workers`, keeping a node on the same worker
+/// as its first parent unless that worker already has its share of the nodes
+pub fn partition(dag: &Dag, workers: usize) -> HashMap<NodeId, usize> {
+    assert!(workers > 0);
+    let share = dag.nodes.len().div_ceil(workers);
+    let parents = dag.parents();
+    let mut loads = vec![0; workers];
+    let mut assignment = HashMap::new();
+    // Visiting parents first, since changes to a `Dag` may give a parent a
+    // larger id than its children
+    dag.topological_order().into_iter().for_each(|id| {
+        let preferred = parents[&id].first()
+            .map(|parent_id| assignment[parent_id])
+            .filter(|worker| loads[*worker] < share);
+        let worker = preferred.unwrap_or_else(|| {
+            (0..workers).min_by_key(|worker| loads[*worker]).unwrap()
+        });
+        loads[worker] += 1;
+        assignment.insert(id, worker);
+    });
+    assignment
+}
+
+enum Event<T> {
+    Response(usize, Response<T>),
+    Disconnected(usize),
+}
+
+type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+struct WorkerConnection {
+    address: Address,
+    writer: Writer,
+    /// Nodes sent to the worker, with the time they were sent
+    in_flight: HashMap<NodeId, Instant>,
+}
+
+/// Executes a `Dag` by dispatching its nodes to worker processes.  Workers
+/// keep the values of the nodes they run and send them to each other, while
+/// the coordinator only receives the values of the sinks and watched nodes.
+/// It reschedules the nodes of a worker that disconnects, or that does not
+/// answer within the dispatch timeout, and runs again the completed nodes
+/// whose values were lost with it.
+pub struct Coordinator<'a> {
+    dag: &'a Dag,
+    workers: Vec<Address>,
+    dispatch_timeout: Option<Duration>,
+    deadline: Option<Duration>,
+    token: CancellationToken,
+    watched: HashSet<NodeId>,
+}
+
+impl<'a> Coordinator<'a> {
+    pub fn new(dag: &'a Dag, workers: Vec<Address>) -> Self {
+        assert!(!workers.is_empty(), "At least one worker is required");
+        let dispatch_timeout = None;
+        let deadline = None;
+        let token = CancellationToken::new();
+        let watched = HashSet::new();
+        Self { dag, workers, dispatch_timeout, deadline, token, watched }
+    }
+
+    /// Sends a node to another worker when its worker has not answered this
+    /// long after it was dispatched, even though it is still connected.  It
+    /// should be longer than any `Operation::timeout`, which workers apply
+    /// themselves.  A worker that takes longer than this to accept a request,
+    /// because it stopped reading, is no longer used at all.
+    pub fn set_dispatch_timeout(&mut self, timeout: Duration) {
+        self.dispatch_timeout = Some(timeout);
+    }
+
+    /// Stops the whole run once `deadline` has elapsed since the start of
+    /// `process`.  Nodes still running on a worker at that point fail, and
+    /// nodes still waiting for inputs are skipped.
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+    }
+
+    /// Token stopping this run when cancelled.  Workers abandon the nodes they
+    /// are running once the coordinator disconnects.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    /// Also sends back the values of the given nodes, kept in
+    /// `Report::values`, without having to make them sinks.  Fails without
+    /// watching any node if one of them is unknown.
+    pub fn watch(&mut self, ids: &[NodeId]) -> Result<(), ComputationError> {
+        if let Some(id) = ids.iter().find(|id| !self.dag.nodes.contains_key(id)) {
+            return Err(ComputationError::UnknownNode(*id));
+        }
+        self.watched.extend(ids);
+        Ok(())
+    }
+
+    pub async fn process<T>(self, initial: T) -> Result<Report<T>, DistributedError>
+    where for<'b> T: Operable<'b, T> + Sendable + Serialize + DeserializeOwned + 'static {
+        let deadline = self.deadline.map(|deadline| Instant::now() + deadline);
+        let (events, mut receiver) = mpsc::unbounded_channel();
+        let mut connections = vec![];
+        for (index, address) in self.workers.iter().enumerate() {
+            let writer: Writer = match address {
+                Address::Tcp(tcp) => spawn_reader(index, TcpStream::connect(tcp).await?, events.clone()),
+                Address::Unix(path) => spawn_reader(index, UnixStream::connect(path).await?, events.clone()),
+            };
+            let address = address.clone();
+            let in_flight = HashMap::new();
+            connections.push(Some(WorkerConnection { address, writer, in_flight }));
+        }
+        drop(events);
+
+        let mut run = Run {
+            coordinator: &self,
+            id: rand::random(),
+            initial,
+            missing: self.dag.parents().iter().map(|(id, parents)| (*id, parents.len())).collect(),
+            parents: self.dag.parents(),
+            assignment: partition(self.dag, connections.len()),
+            connections,
+            ready: self.dag.starts.iter().copied().collect(),
+            holders: HashMap::new(),
+            values: HashMap::new(),
+            statuses: HashMap::new(),
+            attempts: HashMap::new(),
+        };
+        while run.statuses.len() < self.dag.nodes.len() {
+            run.dispatch_ready().await?;
+            let oldest = run.connections.iter().flatten().flat_map(|c| c.in_flight.values()).min().copied();
+            let unresponsive = self.dispatch_timeout.zip(oldest).map(|(limit, start)| start + limit);
+            // Answers that already arrived go before rescheduling any node
+            let receiving = async {
+                match unresponsive {
+                    Some(instant) => timeout_at(instant, receiver.recv()).await.ok(),
+                    None => Some(receiver.recv().await),
+                }
+            };
+            tokio::select! {
+                received = receiving => match received {
+                    Some(Some(event)) => run.handle_event(event)?,
+                    Some(None) => return Err(DistributedError::NoWorkers),
+                    None => run.reschedule_unresponsive(self.dispatch_timeout.unwrap()),
+                },
+                _ = wait_until(deadline) => run.stop(true),
+                _ = self.token.cancelled() => run.stop(false),
+            }
+        }
+
+        let Run { mut values, statuses, attempts, .. } = run;
+        let results = self.dag.nodes.iter()
+            .filter(|(_, node)| node.children.is_empty())
+            .filter_map(|(id, _)| Some((*id, values.get(id)?.clone())))
+            .collect();
+        values.retain(|id, _| self.watched.contains(id));
+        let children = self.dag.nodes.iter().map(|(id, node)| (*id, node.children.clone())).collect();
+        let cancelled = self.token.is_cancelled();
+        Ok(Report {
+            results, statuses, attempts, cancelled, stall: None, cache: Default::default(),
+            values, children, lineage: HashMap::new(),
+        })
+    }
+}
+
+/// Waits until the given time, or forever without one
+async fn wait_until(instant: Option<Instant>) {
+    match instant {
+        Some(instant) => delay_until(instant).await,
+        None => pending().await,
+    }
+}
+
+/// State of a single `Coordinator::process`
+struct Run<'a, T> {
+    coordinator: &'a Coordinator<'a>,
+    id: RunId,
+    initial: T,
+    parents: HashMap<NodeId, Vec<NodeId>>,
+    /// Number of parents of each node without an available value
+    missing: HashMap<NodeId, usize>,
+    connections: Vec<Option<WorkerConnection>>,
+    assignment: HashMap<NodeId, usize>,
+    /// Nodes to dispatch, possibly more than once or no longer runnable
+    ready: VecDeque<NodeId>,
+    /// Worker that computed each node, which may since have been lost
+    holders: HashMap<NodeId, usize>,
+    /// Values sent back to the coordinator, of the sinks and watched nodes
+    values: HashMap<NodeId, T>,
+    statuses: HashMap<NodeId, NodeStatus>,
+    attempts: HashMap<NodeId, Vec<Attempt>>,
+}
+
+impl<T> Run<'_, T>
+where for<'b> T: Operable<'b, T> + Sendable + Serialize + DeserializeOwned + 'static {
+    /// Whether the value of the node can be given to a worker
+    fn is_available(&self, id: NodeId) -> bool {
+        self.values.contains_key(&id) || self.holders.get(&id).is_some_and(|worker| self.connections[*worker].is_some())
+    }
+
+    /// Whether the node still has to run and has all of its inputs
+    fn is_runnable(&self, id: NodeId) -> bool {
+        !self.statuses.contains_key(&id)
+            && !self.connections.iter().flatten().any(|connection| connection.in_flight.contains_key(&id))
+            && self.missing[&id] == 0
+    }
+
+    /// Where the worker running the node finds each of its inputs
+    fn inputs(&self, id: NodeId, worker: usize) -> Inputs<T> {
+        let parents = self.parents[&id].clone();
+        let initial = parents.is_empty().then(|| self.initial.clone());
+        let mut values = vec![];
+        let mut remote = vec![];
+        parents.iter().for_each(|parent_id| match self.values.get(parent_id) {
+            Some(value) => values.push((*parent_id, value.clone())),
+            None if self.holders[parent_id] == worker => {},
+            None => remote.push((*parent_id, self.coordinator.workers[self.holders[parent_id]].clone())),
+        });
+        Inputs { initial, parents, values, remote }
+    }
+
+    async fn dispatch_ready(&mut self) -> Result<(), DistributedError> {
+        let dag = self.coordinator.dag;
+        while let Some(id) = self.ready.pop_front() {
+            if !self.is_runnable(id) {
+                continue;
+            }
+            let worker = match self.connections.get(self.assignment[&id]) {
+                Some(Some(_)) => self.assignment[&id],
+                _ => {
+                    let worker = least_loaded(&self.connections).ok_or(DistributedError::NoWorkers)?;
+                    self.assignment.insert(id, worker);
+                    worker
+                },
+            };
+            let operation = &dag.nodes[&id].operation;
+            let request = Request::Run {
+                run: self.id,
+                id,
+                operation_type: operation.operation_type,
+                timeout: operation.timeout,
+                inputs: self.inputs(id, worker),
+                reply_value: dag.nodes[&id].children.is_empty() || self.coordinator.watched.contains(&id),
+            };
+            let line = to_line(&request)?;
+            debug!(id, worker = %self.coordinator.workers[worker], "dispatching node");
+            let connection = self.connections[worker].as_mut().unwrap();
+            connection.in_flight.insert(id, Instant::now());
+            let writing = connection.writer.write_all(line.as_bytes());
+            let written = match self.coordinator.dispatch_timeout {
+                Some(limit) => timeout(limit, writing).await.map_err(|_| limit),
+                None => Ok(writing.await),
+            };
+            match written {
+                Ok(Ok(())) => {},
+                // The reader notices the disconnection too, and reschedules the node
+                Ok(Err(_)) => continue,
+                // A worker that stopped reading never notices the rest of
+                // a partially written request, so give up on it entirely
+                Err(limit) => self.lose_worker(worker, Some(limit))?,
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_event(&mut self, event: Event<T>) -> Result<(), DistributedError> {
+        let (worker, response) = match event {
+            Event::Response(worker, response) => (worker, response),
+            Event::Disconnected(worker) => return self.lose_worker(worker, None),
+        };
+        let id = match &response {
+            Response::Done { id, .. } | Response::Failed { id, .. } | Response::TimedOut { id, .. }
+            | Response::Unavailable { id, .. } => *id,
+            // Only ever sent to other workers
+            Response::Value { .. } => return Ok(()),
+        };
+        let start = match self.connections[worker].as_mut().and_then(|c| c.in_flight.remove(&id)) {
+            Some(start) => start,
+            // Already rescheduled elsewhere
+            None => return Ok(()),
+        };
+        let dag = self.coordinator.dag;
+        let operation_type = dag.nodes[&id].operation.operation_type;
+        let duration = start.elapsed();
+        let error = match response {
+            Response::Done { value, .. } => {
+                self.attempts.entry(id).or_default().push(Attempt { duration, error: None });
+                self.statuses.insert(id, NodeStatus::Completed);
+                self.holders.insert(id, worker);
+                if let Some(value) = value {
+                    self.values.insert(id, value);
+                }
+                dag.nodes[&id].children.iter().for_each(|child_id| {
+                    let count = self.missing.get_mut(child_id).unwrap();
+                    *count -= 1;
+                    if *count == 0 && !self.statuses.contains_key(child_id) {
+                        self.ready.push_back(*child_id);
+                    }
+                });
+                return Ok(());
+            },
+            Response::Unavailable { input, .. } => {
+                // The worker holding the input cannot be reached, so it is no
+                // longer used, and its values are computed again if needed
+                let holder = self.holders[&input];
+                let worker = self.coordinator.workers[holder].to_string();
+                let error = NodeError::WorkerLost { id, operation_type, worker };
+                warn!(%error, input, "input unavailable");
+                self.attempts.entry(id).or_default().push(Attempt { duration, error: Some(error) });
+                return self.lose_worker(holder, None);
+            },
+            Response::Failed { message, .. } => NodeError::Panicked { id, operation_type, message },
+            Response::TimedOut { timeout, .. } => NodeError::TimedOut { id, operation_type, timeout },
+            Response::Value { .. } => unreachable!(),
+        };
+        warn!(%error, "node failed");
+        self.attempts.entry(id).or_default().push(Attempt { duration, error: Some(error.clone()) });
+        self.statuses.insert(id, NodeStatus::Failed(error));
+        dag.descendants(id).expect("Dispatched an unknown node").into_iter().for_each(|descendant| {
+            self.statuses.entry(descendant).or_insert(NodeStatus::Skipped);
+        });
+        Ok(())
+    }
+
+    /// Stops using the worker, queueing again every node it was running and
+    /// spreading the nodes assigned to it over the other workers.  The nodes
+    /// fail with `WorkerUnresponsive` if the worker stopped reading for
+    /// `unresponsive`, otherwise with `WorkerLost`.
+    fn lose_worker(&mut self, worker: usize, unresponsive: Option<Duration>) -> Result<(), DistributedError> {
+        if let Some(connection) = self.connections[worker].take() {
+            warn!(worker = %connection.address, "worker lost, rescheduling its nodes");
+            let address = connection.address.to_string();
+            let mut lost: Vec<(NodeId, Instant)> = connection.in_flight.into_iter().collect();
+            lost.sort_unstable();
+            lost.into_iter().for_each(|(id, start)| {
+                let operation_type = self.coordinator.dag.nodes[&id].operation.operation_type;
+                let worker = address.clone();
+                let error = match unresponsive {
+                    Some(timeout) => NodeError::WorkerUnresponsive { id, operation_type, worker, timeout },
+                    None => NodeError::WorkerLost { id, operation_type, worker },
+                };
+                let duration = start.elapsed();
+                self.attempts.entry(id).or_default().push(Attempt { duration, error: Some(error) });
+            });
+            // Spread the nodes of the lost worker over the others, including the
+            // finished ones, which are only dispatched again if their value is lost
+            let alive: Vec<usize> = (0..self.connections.len()).filter(|w| self.connections[*w].is_some()).collect();
+            if alive.is_empty() {
+                return Err(DistributedError::NoWorkers);
+            }
+            let mut moved: Vec<NodeId> = self.assignment.iter()
+                .filter(|(_, assigned)| **assigned == worker)
+                .map(|(id, _)| *id)
+                .collect();
+            moved.sort_unstable();
+            moved.into_iter().enumerate().for_each(|(index, id)| {
+                self.assignment.insert(id, alive[index % alive.len()]);
+            });
+        }
+        self.queue_lost_nodes();
+        Ok(())
+    }
+
+    /// Runs again every completed node whose value was lost with its worker
+    /// while a child still needs it, along with its own ancestors in the same
+    /// situation, then queues every node that can run
+    fn queue_lost_nodes(&mut self) {
+        let dag = self.coordinator.dag;
+        loop {
+            let lost: Vec<NodeId> = self.statuses.iter()
+                .filter(|(id, status)| **status == NodeStatus::Completed && !self.is_available(**id))
+                .filter(|(id, _)| dag.nodes[*id].children.iter().any(|child_id| !self.statuses.contains_key(child_id)))
+                .map(|(id, _)| *id)
+                .collect();
+            if lost.is_empty() {
+                break;
+            }
+            debug!(?lost, "running nodes with lost values again");
+            lost.iter().for_each(|id| { self.statuses.remove(id); });
+        }
+        let parents = &self.parents;
+        let missing = parents.iter()
+            .map(|(id, parents)| (*id, parents.iter().filter(|parent_id| !self.is_available(**parent_id)).count()))
+            .collect();
+        self.missing = missing;
+        let mut runnable: Vec<NodeId> = dag.nodes.keys().copied().filter(|id| self.is_runnable(*id)).collect();
+        runnable.sort_unstable();
+        self.ready.extend(runnable);
+    }
+
+    /// Takes back every node dispatched more than `limit` ago, queueing it
+    /// again on the least loaded of the other workers.  A late answer from the
+    /// first worker is then ignored, like one from a worker that disconnected.
+    fn reschedule_unresponsive(&mut self, limit: Duration) {
+        for worker in 0..self.connections.len() {
+            let connection = match self.connections[worker].as_mut() {
+                Some(connection) => connection,
+                None => continue,
+            };
+            let mut hung: Vec<(NodeId, Instant)> = connection.in_flight.iter()
+                .filter(|(_, start)| start.elapsed() >= limit)
+                .map(|(id, start)| (*id, *start))
+                .collect();
+            hung.sort_unstable();
+            hung.iter().for_each(|(id, _)| { connection.in_flight.remove(id); });
+            let address = connection.address.to_string();
+            let other = (0..self.connections.len())
+                .filter(|other| *other != worker && self.connections[*other].is_some())
+                .min_by_key(|other| self.connections[*other].as_ref().unwrap().in_flight.len())
+                .unwrap_or(worker);
+            hung.into_iter().for_each(|(id, start)| {
+                let operation_type = self.coordinator.dag.nodes[&id].operation.operation_type;
+                let worker = address.clone();
+                let error = NodeError::WorkerUnresponsive { id, operation_type, worker, timeout: limit };
+                warn!(%error, "rescheduling node");
+                self.attempts.entry(id).or_default().push(Attempt { duration: start.elapsed(), error: Some(error) });
+                self.assignment.insert(id, other);
+                self.ready.push_back(id);
+            });
+        }
+    }
+
+    /// Gives up on every unfinished node, once past the deadline or cancelled.
+    /// Past the deadline, running nodes fail and the others are skipped,
+    /// otherwise they are all cancelled.
+    fn stop(&mut self, deadline: bool) {
+        let dag = self.coordinator.dag;
+        for connection in self.connections.iter_mut().flatten() {
+            for (id, start) in connection.in_flight.drain() {
+                let status = if deadline {
+                    let operation_type = dag.nodes[&id].operation.operation_type;
+                    let error = NodeError::DeadlineExceeded { id, operation_type };
+                    self.attempts.entry(id).or_default().push(Attempt { duration: start.elapsed(), error: Some(error.clone()) });
+                    NodeStatus::Failed(error)
+                } else {
+                    NodeStatus::Cancelled
+                };
+                self.statuses.insert(id, status);
+            }
+        }
+        dag.nodes.keys().for_each(|id| {
+            self.statuses.entry(*id).or_insert(if deadline { NodeStatus::Skipped } else { NodeStatus::Cancelled });
+        });
+    }
+}
+
+fn least_loaded(connections: &[Option<WorkerConnection>]) -> Option<usize> {
+    (0..connections.len())
+        .filter(|worker| connections[*worker].is_some())
+        .min_by_key(|worker| connections[*worker].as_ref().unwrap().in_flight.len())
+}
+
+/// Forwards every response from the worker as an event, returning the writing
+/// half of the connection
+fn spawn_reader<T, S>(worker: usize, stream: S, events: mpsc::UnboundedSender<Event<T>>) -> Writer
+where T: DeserializeOwned + Send + 'static, S: AsyncRead + AsyncWrite + Send + 'static {
+    let (reader, writer) = tokio::io::split(stream);
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            match serde_json::from_str(&line) {
+                Ok(response) => {
+                    let _ = events.send(Event::Response(worker, response));
+                },
+                Err(_) => break,
+            }
+        }
+        let _ = events.send(Event::Disconnected(worker));
+    });
+    Box::new(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::future::pending;
+
+    use crate::computation::Computation;
+
+    async fn start_worker(token: CancellationToken) -> Address {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = Address::Tcp(listener.local_addr().unwrap().to_string());
+        tokio::spawn(serve_tcp::<u64>(listener, token));
+        address
+    }
+
+    /// Worker that accepts a connection, reads a single request, then dies
+    async fn start_dying_worker() -> Address {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = Address::Tcp(listener.local_addr().unwrap().to_string());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            lines.next_line().await.unwrap();
+        });
+        address
+    }
+
+    /// Worker that stays connected and reads every request, but never answers
+    async fn start_hung_worker() -> Address {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = Address::Tcp(listener.local_addr().unwrap().to_string());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            while let Ok(Some(_)) = lines.next_line().await {}
+        });
+        address
+    }
+
+    /// Worker that accepts a connection but never reads from it
+    async fn start_deaf_worker() -> Address {
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = Address::Tcp(listener.local_addr().unwrap().to_string());
+        tokio::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            pending::<()>().await;
+        });
+        address
+    }
+
+    /// Worker that answers the first request, then disconnects, dropping the
+    /// values it kept
+    async fn start_forgetful_worker(token: CancellationToken) -> Address {
+        let worker = match start_worker(token).await {
+            Address::Tcp(address) => address,
+            Address::Unix(_) => unreachable!(),
+        };
+        let mut listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = Address::Tcp(listener.local_addr().unwrap().to_string());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = tokio::io::split(stream);
+            let mut requests = BufReader::new(reader).lines();
+            let (worker_reader, mut worker_writer) = tokio::io::split(TcpStream::connect(worker).await.unwrap());
+            let mut responses = BufReader::new(worker_reader).lines();
+            let request = requests.next_line().await.unwrap().unwrap();
+            worker_writer.write_all(format!("{}\n", request).as_bytes()).await.unwrap();
+            let response = responses.next_line().await.unwrap().unwrap();
+            writer.write_all(format!("{}\n", response).as_bytes()).await.unwrap();
+        });
+        address
+    }
+
+    /// Connection sending requests to a worker, one at a time
+    struct Client {
+        lines: tokio::io::Lines<BufReader<tokio::io::ReadHalf<TcpStream>>>,
+        writer: tokio::io::WriteHalf<TcpStream>,
+    }
+
+    impl Client {
+        async fn connect(address: &Address) -> Self {
+            let stream = match address {
+                Address::Tcp(address) => TcpStream::connect(address).await.unwrap(),
+                Address::Unix(_) => unreachable!(),
+            };
+            let (reader, writer) = tokio::io::split(stream);
+            Self { lines: BufReader::new(reader).lines(), writer }
+        }
+
+        async fn send(&mut self, request: Request<u64>) -> Response<u64> {
+            self.writer.write_all(to_line(&request).unwrap().as_bytes()).await.unwrap();
+            serde_json::from_str(&self.lines.next_line().await.unwrap().unwrap()).unwrap()
+        }
+    }
+
+    fn run_request(id: NodeId, inputs: Inputs<u64>, reply_value: bool) -> Request<u64> {
+        Request::Run { run: 1, id, operation_type: OperationType::Sum, timeout: None, inputs, reply_value }
+    }
+
+    fn sum_dag() -> Dag {
+        let mut dag: Dag = Default::default();
+        let sum = Operation::new(OperationType::Sum);
+        let product = Operation::new(OperationType::Product);
+        let id1 = dag.add_node(sum.clone(), vec![]);
+        let id2 = dag.add_node(sum.clone(), vec![]);
+        let id3 = dag.add_node(product.clone(), vec![id1, id2]);
+        let id4 = dag.add_node(sum.clone(), vec![id1, id3]);
+        let id5 = dag.add_node(product.clone(), vec![id3, id4]);
+        dag.add_node(sum.clone(), vec![id5, id2]);
+        dag.add_node(sum.clone(), vec![id4]);
+        dag
+    }
+
+    #[test]
+    pub fn parse_address() {
+        assert_eq!("127.0.0.1:7000".parse(), Ok(Address::Tcp("127.0.0.1:7000".to_owned())));
+        assert_eq!("unix:/tmp/worker".parse(), Ok(Address::Unix(PathBuf::from("/tmp/worker"))));
+    }
+
+    #[test]
+    pub fn partition_balanced() {
+        let dag = sum_dag();
+        let assignment = partition(&dag, 3);
+        assert_eq!(assignment.len(), dag.nodes.len());
+        (0..3).for_each(|worker| {
+            let load = assignment.values().filter(|assigned| **assigned == worker).count();
+            assert!(load > 0 && load <= 3);
+        });
+    }
+
+    #[test]
+    pub fn partition_parent_with_larger_id() {
+        let mut dag = sum_dag();
+        let start = dag.starts[0];
+        let id = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        dag.add_edge(id, start).unwrap();
+        let assignment = partition(&dag, 2);
+        assert_eq!(assignment.len(), dag.nodes.len());
+        assert_eq!(assignment[&start], assignment[&id]);
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn matches_local_computation() {
+        let dag = sum_dag();
+        let token = CancellationToken::new();
+        let workers = vec![start_worker(token.clone()).await, start_worker(token.clone()).await];
+        let report = Coordinator::new(&dag, workers).process(2u64).await.unwrap();
+        let local = Computation::new(&dag).process(2u64).await;
+        assert!(report.is_success());
+        assert_eq!(report.results, local.results);
+        token.cancel();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn unix_socket_worker() {
+        let path = std::env::temp_dir().join(format!("dag-worker-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let address = Address::Unix(path.clone());
+        let token = CancellationToken::new();
+        let listening = address.clone();
+        let serving = token.clone();
+        tokio::spawn(async move { serve::<u64>(&listening, serving).await });
+        while !path.exists() {
+            tokio::time::delay_for(std::time::Duration::from_millis(1)).await;
+        }
+        let dag = sum_dag();
+        let report = Coordinator::new(&dag, vec![address]).process(2u64).await.unwrap();
+        let local = Computation::new(&dag).process(2u64).await;
+        assert_eq!(report.results, local.results);
+        token.cancel();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn reschedules_lost_nodes() {
+        let dag = sum_dag();
+        let token = CancellationToken::new();
+        let workers = vec![start_dying_worker().await, start_worker(token.clone()).await];
+        let report = Coordinator::new(&dag, workers).process(2u64).await.unwrap();
+        let local = Computation::new(&dag).process(2u64).await;
+        assert!(report.is_success());
+        assert_eq!(report.results, local.results);
+        let lost: Vec<&Attempt> = report.attempts.values().flatten()
+            .filter(|attempt| matches!(attempt.error, Some(NodeError::WorkerLost { .. })))
+            .collect();
+        assert!(!lost.is_empty());
+        token.cancel();
+    }
+
+    #[tokio::test]
+    pub async fn no_workers_left() {
+        let dag = sum_dag();
+        let workers = vec![start_dying_worker().await];
+        match Coordinator::new(&dag, workers).process(2u64).await {
+            Err(DistributedError::NoWorkers) => (),
+            _ => panic!("Computation finished without workers"),
+        }
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn reschedules_unresponsive_nodes() {
+        let dag = sum_dag();
+        let token = CancellationToken::new();
+        let workers = vec![start_hung_worker().await, start_worker(token.clone()).await];
+        let mut coordinator = Coordinator::new(&dag, workers);
+        coordinator.set_dispatch_timeout(Duration::from_millis(50));
+        let processing = coordinator.process(2u64);
+        let report = timeout(Duration::from_secs(5), processing).await.expect("Hung worker blocked the computation");
+        let report = report.unwrap();
+        let local = Computation::new(&dag).process(2u64).await;
+        assert!(report.is_success());
+        assert_eq!(report.results, local.results);
+        let unresponsive: Vec<&Attempt> = report.attempts.values().flatten()
+            .filter(|attempt| matches!(attempt.error, Some(NodeError::WorkerUnresponsive { .. })))
+            .collect();
+        assert!(!unresponsive.is_empty());
+        token.cancel();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn reschedules_nodes_of_worker_not_reading() {
+        // Requests large enough to fill the socket buffers of the deaf worker
+        let mut dag: Dag = Default::default();
+        let sum = Operation::new(OperationType::Sum);
+        let starts: Vec<NodeId> = (0..400).map(|_| dag.add_node(sum.clone(), vec![])).collect();
+        (0..1_200).for_each(|_| { dag.add_node(sum.clone(), starts.clone()); });
+        let token = CancellationToken::new();
+        let workers = vec![start_deaf_worker().await, start_worker(token.clone()).await];
+        let mut coordinator = Coordinator::new(&dag, workers);
+        coordinator.set_dispatch_timeout(Duration::from_millis(200));
+        let processing = coordinator.process(1u64 << 50);
+        let report = timeout(Duration::from_secs(10), processing).await.expect("Deaf worker blocked the computation");
+        let report = report.unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.results.len(), 1_200);
+        assert!(report.results.values().all(|value| *value == 400 << 50));
+        let unresponsive = report.attempts.values().flatten()
+            .filter(|attempt| matches!(attempt.error, Some(NodeError::WorkerUnresponsive { .. })))
+            .count();
+        assert!(unresponsive > 0);
+        token.cancel();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn worker_applies_operation_timeout() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Delay);
+        operation.timeout = Some(Duration::from_millis(50));
+        let id1 = dag.add_node(operation, vec![]);
+        dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let token = CancellationToken::new();
+        let workers = vec![start_worker(token.clone()).await];
+        let start = Instant::now();
+        let report = Coordinator::new(&dag, workers).process(2u64).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        let error = NodeError::TimedOut {
+            id: id1, operation_type: OperationType::Delay, timeout: Duration::from_millis(50),
+        };
+        assert_eq!(report.statuses[&id1], NodeStatus::Failed(error));
+        token.cancel();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn fetches_inputs_from_other_workers() {
+        let token = CancellationToken::new();
+        let (first, second) = (start_worker(token.clone()).await, start_worker(token.clone()).await);
+        let mut coordinator = Client::connect(&first).await;
+        let initial = Inputs { initial: Some(2), parents: vec![], values: vec![], remote: vec![] };
+        let response = coordinator.send(run_request(1, initial, false)).await;
+        assert!(matches!(response, Response::Done { id: 1, value: None }));
+
+        let mut other = Client::connect(&second).await;
+        let inputs = Inputs { initial: None, parents: vec![1, 2], values: vec![(2, 3)], remote: vec![(1, first.clone())] };
+        let response = other.send(run_request(3, inputs, true)).await;
+        assert!(matches!(response, Response::Done { id: 3, value: Some(5) }));
+        // The second worker never computed node 1
+        let inputs = Inputs { initial: None, parents: vec![1], values: vec![], remote: vec![] };
+        let response = other.send(run_request(4, inputs, true)).await;
+        assert!(matches!(response, Response::Unavailable { id: 4, input: 1 }));
+
+        // Values are dropped with the connection of the coordinator
+        drop(coordinator);
+        let mut dropped = false;
+        for _ in 0..100 {
+            let inputs = Inputs { initial: None, parents: vec![1], values: vec![], remote: vec![(1, first.clone())] };
+            if let Response::Unavailable { .. } = other.send(run_request(5, inputs, true)).await {
+                dropped = true;
+                break;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        assert!(dropped);
+        token.cancel();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn recomputes_values_of_lost_worker() {
+        let mut dag: Dag = Default::default();
+        let sum = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(sum.clone(), vec![]);
+        let id2 = dag.add_node(sum.clone(), vec![id1]);
+        dag.add_node(sum, vec![id2]);
+        let token = CancellationToken::new();
+        // The first node runs on the forgetful worker, which then loses its value
+        let workers = vec![start_forgetful_worker(token.clone()).await, start_worker(token.clone()).await];
+        let report = Coordinator::new(&dag, workers).process(2u64).await.unwrap();
+        let local = Computation::new(&dag).process(2u64).await;
+        assert!(report.is_success());
+        assert_eq!(report.results, local.results);
+        let completed = report.attempts[&id1].iter().filter(|attempt| attempt.error.is_none()).count();
+        assert_eq!(completed, 2);
+        token.cancel();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn watched_values() {
+        let dag = sum_dag();
+        let token = CancellationToken::new();
+        let workers = vec![start_worker(token.clone()).await, start_worker(token.clone()).await];
+        let watched = dag.topological_order()[2];
+        let mut coordinator = Coordinator::new(&dag, workers);
+        coordinator.watch(&[watched]).unwrap();
+        assert_eq!(coordinator.watch(&[watched, 100]), Err(ComputationError::UnknownNode(100)));
+        let report = coordinator.process(2u64).await.unwrap();
+        let mut computation = Computation::new(&dag);
+        computation.watch(&[watched]).unwrap();
+        let local = computation.process(2u64).await;
+        assert_eq!(report.values, local.values);
+        assert_eq!(report.results, local.results);
+        token.cancel();
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn deadline_exceeded() {
+        let dag = sum_dag();
+        let start = dag.starts[0];
+        let mut coordinator = Coordinator::new(&dag, vec![start_hung_worker().await]);
+        coordinator.set_deadline(Duration::from_millis(50));
+        let report = coordinator.process(2u64).await.unwrap();
+        assert!(!report.cancelled);
+        let error = NodeError::DeadlineExceeded { id: start, operation_type: OperationType::Sum };
+        assert_eq!(report.statuses[&start], NodeStatus::Failed(error));
+        assert_eq!(report.skipped().len(), dag.nodes.len() - dag.starts.len());
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn cancelled() {
+        let dag = sum_dag();
+        let coordinator = Coordinator::new(&dag, vec![start_hung_worker().await]);
+        let token = coordinator.cancellation_token();
+        tokio::spawn(async move {
+            tokio::time::delay_for(Duration::from_millis(50)).await;
+            token.cancel();
+        });
+        let report = coordinator.process(2u64).await.unwrap();
+        assert!(report.cancelled);
+        assert!(report.statuses.values().all(|status| *status == NodeStatus::Cancelled));
+        assert!(report.results.is_empty());
+    }
+}
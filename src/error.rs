@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+use crate::dag::NodeId;
+use crate::operation::OperationType;
+
+/// Reasons why a single node of a computation could not produce a value
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeError {
+    /// The operation ran longer than its own `timeout`
+    TimedOut { id: NodeId, operation_type: OperationType, timeout: Duration },
+    /// The operation was still running when the computation deadline passed
+    DeadlineExceeded { id: NodeId, operation_type: OperationType },
+    /// The operation panicked while running
+    Panicked { id: NodeId, operation_type: OperationType, message: String },
+    /// The worker process running the operation disconnected
+    WorkerLost { id: NodeId, operation_type: OperationType, worker: String },
+    /// The worker running the operation did not answer within the dispatch timeout
+    WorkerUnresponsive { id: NodeId, operation_type: OperationType, worker: String, timeout: Duration },
+    /// A hook refused to run the node, usually because of invalid inputs
+    Rejected { id: NodeId, operation_type: OperationType, message: String },
+    /// A `FaultInjector` made the attempt fail on purpose
+    Injected { id: NodeId, operation_type: OperationType },
+}
+
+impl NodeError {
+    pub fn id(&self) -> NodeId {
+        match self {
+            NodeError::TimedOut { id, .. } => *id,
+            NodeError::DeadlineExceeded { id, .. } => *id,
+            NodeError::Panicked { id, .. } => *id,
+            NodeError::WorkerLost { id, .. } => *id,
+            NodeError::WorkerUnresponsive { id, .. } => *id,
+            NodeError::Rejected { id, .. } => *id,
+            NodeError::Injected { id, .. } => *id,
+        }
+    }
+
+    pub fn operation_type(&self) -> OperationType {
+        match self {
+            NodeError::TimedOut { operation_type, .. } => *operation_type,
+            NodeError::DeadlineExceeded { operation_type, .. } => *operation_type,
+            NodeError::Panicked { operation_type, .. } => *operation_type,
+            NodeError::WorkerLost { operation_type, .. } => *operation_type,
+            NodeError::WorkerUnresponsive { operation_type, .. } => *operation_type,
+            NodeError::Rejected { operation_type, .. } => *operation_type,
+            NodeError::Injected { operation_type, .. } => *operation_type,
+        }
+    }
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeError::TimedOut { id, operation_type, timeout } =>
+                write!(f, "node {} ({:?}) timed out after {:?}", id, operation_type, timeout),
+            NodeError::DeadlineExceeded { id, operation_type } =>
+                write!(f, "node {} ({:?}) was still running at the computation deadline", id, operation_type),
+            NodeError::Panicked { id, operation_type, message } =>
+                write!(f, "node {} ({:?}) panicked: {}", id, operation_type, message),
+            NodeError::WorkerLost { id, operation_type, worker } =>
+                write!(f, "node {} ({:?}) lost when worker {} disconnected", id, operation_type, worker),
+            NodeError::WorkerUnresponsive { id, operation_type, worker, timeout } =>
+                write!(f, "node {} ({:?}) got no answer from worker {} within {:?}", id, operation_type, worker, timeout),
+            NodeError::Rejected { id, operation_type, message } =>
+                write!(f, "node {} ({:?}) rejected: {}", id, operation_type, message),
+            NodeError::Injected { id, operation_type } =>
+                write!(f, "node {} ({:?}) failed with an injected fault", id, operation_type),
+        }
+    }
+}
+
+impl Error for NodeError {}
+
+/// Reasons why a `Computation` cannot be set up as requested, leaving it unchanged
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ComputationError {
+    /// The node is not part of the `Dag`
+    UnknownNode(NodeId),
+    /// The node was pruned by `Computation::set_targets`, so it never runs
+    PrunedNode(NodeId),
+    /// The node has parents, so it never receives an input of its own
+    NotStartNode(NodeId),
+}
+
+impl fmt::Display for ComputationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComputationError::UnknownNode(id) => write!(f, "unknown node {}", id),
+            ComputationError::PrunedNode(id) => write!(f, "node {} is not needed by the targets", id),
+            ComputationError::NotStartNode(id) => write!(f, "node {} is not a start node", id),
+        }
+    }
+}
+
+impl Error for ComputationError {}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hasher;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::dag::NodeId;
+use crate::hash::StableHasher;
+
+/// What happens to an attempt of a node chosen by a `FaultInjector`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Fails with `NodeError::Injected` without running the operation
+    Fail,
+    /// Never finishes, until the operation timeout, the deadline, or a cancellation
+    Hang,
+    /// Waits this long, within the operation timeout, before running the operation
+    Delay(Duration),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseFaultError(String);
+
+impl fmt::Display for ParseFaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fault {:?}, expected \"fail\", \"hang\", or \"delay:<milliseconds>\"", self.0)
+    }
+}
+
+impl Error for ParseFaultError {}
+
+/// Probability of random faults outside of 0 to 1, or not a number
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidProbability(pub f64);
+
+impl fmt::Display for InvalidProbability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fault probability {}, expected a number between 0 and 1", self.0)
+    }
+}
+
+impl Error for InvalidProbability {}
+
+impl FromStr for Fault {
+    type Err = ParseFaultError;
+
+    /// Parses `fail`, `hang`, or `delay:<milliseconds>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseFaultError(s.to_owned());
+        match s {
+            "fail" => Ok(Fault::Fail),
+            "hang" => Ok(Fault::Hang),
+            _ => {
+                let milliseconds = s.strip_prefix("delay:").ok_or_else(error)?;
+                let milliseconds = milliseconds.parse().map_err(|_| error())?;
+                Ok(Fault::Delay(Duration::from_millis(milliseconds)))
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct RandomFaults {
+    seed: u64,
+    probability: f64,
+    fault: Fault,
+}
+
+/// Chooses the attempts of nodes to disrupt, to test retries, timeouts, and
+/// error handling without changing the operations.  Nodes are chosen
+/// explicitly by id, or at random with a seed, in which case every attempt of
+/// every node is drawn independently, but the same way on every run, platform
+/// and Rust release.
+#[derive(Clone, Debug, Default)]
+pub struct FaultInjector {
+    /// Fault of every chosen node, and how many of its first attempts it
+    /// affects, or all of them if `None`
+    nodes: HashMap<NodeId, (Fault, Option<u32>)>,
+    random: Option<RandomFaults>,
+}
+
+impl FaultInjector {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Disrupts every attempt of any node with the given probability, which
+    /// must be between 0 and 1
+    pub fn random(seed: u64, probability: f64, fault: Fault) -> Result<Self, InvalidProbability> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(InvalidProbability(probability));
+        }
+        let random = Some(RandomFaults { seed, probability, fault });
+        Ok(Self { random, ..Default::default() })
+    }
+
+    /// Disrupts every attempt of the given node, whatever the random draw
+    pub fn inject(&mut self, id: NodeId, fault: Fault) {
+        self.nodes.insert(id, (fault, None));
+    }
+
+    /// Only disrupts the first `attempts` attempts of the given node, so that
+    /// it can succeed when retried
+    pub fn inject_attempts(&mut self, id: NodeId, fault: Fault, attempts: u32) {
+        self.nodes.insert(id, (fault, Some(attempts)));
+    }
+
+    /// Fault of the given attempt of the node, counting from 1, if any
+    pub fn fault(&self, id: NodeId, attempt: u32) -> Option<Fault> {
+        if let Some((fault, attempts)) = self.nodes.get(&id) {
+            return attempts.is_none_or(|attempts| attempt <= attempts).then_some(*fault);
+        }
+        let random = self.random?;
+        let mut hasher = StableHasher::new();
+        hasher.write_u64(random.seed);
+        hasher.write_u64(id);
+        hasher.write_u32(attempt);
+        let draw = hasher.finish() as f64 / u64::MAX as f64;
+        (draw < random.probability).then_some(random.fault)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use tokio::time::Instant;
+
+    use crate::computation::Computation;
+    use crate::dag::Dag;
+    use crate::error::NodeError;
+    use crate::operation::{Operation, OperationType};
+    use crate::report::NodeStatus;
+    use crate::retry::{Backoff, RetryPolicy};
+
+    fn chain() -> (Dag, NodeId, NodeId) {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        (dag, id1, id2)
+    }
+
+    #[test]
+    pub fn parses_faults() {
+        assert_eq!("fail".parse(), Ok(Fault::Fail));
+        assert_eq!("hang".parse(), Ok(Fault::Hang));
+        assert_eq!("delay:250".parse(), Ok(Fault::Delay(Duration::from_millis(250))));
+        assert!("delay".parse::<Fault>().is_err());
+        assert!("crash".parse::<Fault>().is_err());
+    }
+
+    #[test]
+    pub fn random_faults_are_reproducible() {
+        let injector = FaultInjector::random(7, 0.3, Fault::Fail).unwrap();
+        let faulty: Vec<NodeId> = (0..1000).filter(|id| injector.fault(*id, 1).is_some()).collect();
+        let again: Vec<NodeId> = (0..1000).filter(|id| injector.fault(*id, 1).is_some()).collect();
+        assert_eq!(faulty, again);
+        assert!(faulty.len() > 200 && faulty.len() < 400);
+        assert!((0..100).all(|id| FaultInjector::random(7, 0.0, Fault::Fail).unwrap().fault(id, 1).is_none()));
+        assert!((0..100).all(|id| FaultInjector::random(7, 1.0, Fault::Fail).unwrap().fault(id, 1).is_some()));
+    }
+
+    #[test]
+    pub fn invalid_probability() {
+        assert_eq!(FaultInjector::random(7, 2.0, Fault::Fail).err(), Some(InvalidProbability(2.0)));
+        assert_eq!(FaultInjector::random(7, -0.5, Fault::Fail).err(), Some(InvalidProbability(-0.5)));
+        assert!(FaultInjector::random(7, f64::NAN, Fault::Fail).is_err());
+    }
+
+    #[test]
+    pub fn random_faults_are_stable() {
+        // Changing these breaks the runs reproduced from a seed
+        let injector = FaultInjector::random(7, 0.3, Fault::Fail).unwrap();
+        let faulty: Vec<NodeId> = (0..33).filter(|id| injector.fault(*id, 1).is_some()).collect();
+        assert_eq!(faulty, vec![9, 13, 15, 16, 19, 20, 22, 32]);
+        let attempts: Vec<u32> = (1..=8).filter(|attempt| injector.fault(9, *attempt).is_some()).collect();
+        assert_eq!(attempts, vec![1, 3, 6]);
+    }
+
+    #[tokio::test]
+    pub async fn injected_failure_skips_descendants() {
+        let (dag, id1, id2) = chain();
+        let mut injector = FaultInjector::new();
+        injector.inject(id1, Fault::Fail);
+        let mut computation = Computation::new(&dag);
+        computation.set_fault_injector(injector);
+        let report = computation.process(1u64).await;
+        let error = NodeError::Injected { id: id1, operation_type: OperationType::Sum };
+        assert_eq!(report.statuses[&id1], NodeStatus::Failed(error));
+        assert_eq!(report.statuses[&id2], NodeStatus::Skipped);
+    }
+
+    #[tokio::test]
+    pub async fn retried_until_fault_stops() {
+        let (dag, id1, id2) = chain();
+        let mut injector = FaultInjector::new();
+        injector.inject_attempts(id1, Fault::Fail, 2);
+        let mut computation = Computation::new(&dag);
+        computation.set_fault_injector(injector);
+        computation.set_retry_policy(id1, RetryPolicy::new(3, Backoff::Fixed(Duration::from_millis(1))));
+        let report = computation.process(1u64).await;
+        assert!(report.is_success());
+        assert_eq!(report.attempts[&id1].len(), 3);
+        assert_eq!(report.statuses[&id2], NodeStatus::Completed);
+    }
+
+    #[tokio::test]
+    pub async fn hang_times_out() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Sum);
+        operation.timeout = Some(Duration::from_millis(50));
+        let id = dag.add_node(operation, vec![]);
+        let mut injector = FaultInjector::new();
+        injector.inject(id, Fault::Hang);
+        let mut computation = Computation::new(&dag);
+        computation.set_fault_injector(injector);
+        let report = computation.process(1u64).await;
+        let error = NodeError::TimedOut { id, operation_type: OperationType::Sum, timeout: Duration::from_millis(50) };
+        assert_eq!(report.statuses[&id], NodeStatus::Failed(error));
+    }
+
+    #[tokio::test]
+    pub async fn delayed_result() {
+        let (dag, id1, id2) = chain();
+        let mut injector = FaultInjector::new();
+        injector.inject(id1, Fault::Delay(Duration::from_millis(100)));
+        let mut computation = Computation::new(&dag);
+        computation.set_fault_injector(injector);
+        let start = Instant::now();
+        let report = computation.process(1u64).await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(report.results, BTreeMap::from([(id2, 1)]));
+    }
+}
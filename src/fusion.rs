@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use crate::dag::NodeId;
+
+/// Groups the nodes into maximal chains, in which every node but the last has
+/// a single child, and every node but the first has that single parent.  Each
+/// chain runs as one task, its nodes one after the other, each passing its
+/// result straight to the next, since none of them could run alongside
+/// another anyway.  Nodes outside of any longer chain are chains of their
+/// own.  Chains are ordered by the id of their first node.
+pub(crate) fn chains(parents: &HashMap<NodeId, Vec<NodeId>>) -> Vec<Vec<NodeId>> {
+    let mut children: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+    parents.iter().for_each(|(id, node_parents)| {
+        node_parents.iter().for_each(|parent_id| children.entry(*parent_id).or_default().push(*id));
+    });
+    let next = |id: &NodeId| match children.get(id).map(Vec::as_slice) {
+        Some([child_id]) if parents[child_id].len() == 1 => Some(*child_id),
+        _ => None,
+    };
+
+    let mut heads: Vec<NodeId> = parents.iter()
+        .filter(|(id, node_parents)| match node_parents.as_slice() {
+            [parent_id] => next(parent_id) != Some(**id),
+            _ => true,
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    heads.sort_unstable();
+    heads.into_iter()
+        .map(|head| {
+            let mut chain = vec![head];
+            while let Some(id) = next(chain.last().unwrap()) {
+                chain.push(id);
+            }
+            chain
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parents(edges: &[(NodeId, NodeId)], nodes: NodeId) -> HashMap<NodeId, Vec<NodeId>> {
+        let mut parents: HashMap<NodeId, Vec<NodeId>> = (0..nodes).map(|id| (id, vec![])).collect();
+        edges.iter().for_each(|(parent_id, id)| parents.get_mut(id).unwrap().push(*parent_id));
+        parents
+    }
+
+    #[test]
+    pub fn fuses_single_chain() {
+        let parents = parents(&[(0, 1), (1, 2), (2, 3)], 4);
+        assert_eq!(chains(&parents), vec![vec![0, 1, 2, 3]]);
+    }
+
+    #[test]
+    pub fn stops_at_fan_out_and_fan_in() {
+        // 0 -> 1 -> 2 splits into 3 -> 4 and 5, which join in 6 -> 7
+        let edges = [(0, 1), (1, 2), (2, 3), (3, 4), (2, 5), (4, 6), (5, 6), (6, 7)];
+        let parents = parents(&edges, 8);
+        assert_eq!(chains(&parents), vec![vec![0, 1, 2], vec![3, 4], vec![5], vec![6, 7]]);
+    }
+
+    #[test]
+    pub fn every_node_in_one_chain() {
+        let edges = [(0, 2), (1, 2), (2, 3), (4, 3), (3, 5)];
+        let parents = parents(&edges, 7);
+        let chains = chains(&parents);
+        let mut ids: Vec<NodeId> = chains.iter().flatten().copied().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, (0..7).collect::<Vec<_>>());
+        assert_eq!(chains, vec![vec![0], vec![1], vec![2], vec![3, 5], vec![4], vec![6]]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::Arc;
+
+use crate::computation::panic_message;
+use crate::dag::NodeId;
+use crate::error::NodeError;
+use crate::operation::Operation;
+
+/// Behavior added around the operation of every node, without changing the
+/// operations themselves.  Hooks are chained on a `Computation`: `before`
+/// runs in the order the hooks were added, and `after` in the reverse order,
+/// so that the first hook added wraps all the others.
+pub trait NodeHook<T>: Send + Sync {
+    /// Called once the inputs of the node are ready, before looking up the
+    /// cache or running the operation.  The inputs may be changed, or the node
+    /// rejected with a message, failing it without running the operation.
+    /// Inputs are shared with the other children of the same parents, so a
+    /// changed input is replaced rather than modified in place.
+    fn before(&self, _id: NodeId, _operation: &Operation, _inputs: &mut Vec<Arc<T>>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called with the outcome of the node, which may be replaced, before it is
+    /// sent to the children.  Only called if `before` succeeded, including
+    /// when a later hook rejected the node.
+    fn after(&self, _id: NodeId, _operation: &Operation, _inputs: &[Arc<T>], _result: &mut Result<T, NodeError>) {}
+}
+
+/// Runs `before` on every hook in order, stopping at the first rejection or
+/// panic, which fails the node.  Also returns how many hooks succeeded, which
+/// are the only ones to run `after`.
+pub(crate) fn before<T>(hooks: &[Box<dyn NodeHook<T>>], id: NodeId, operation: &Operation, inputs: &mut Vec<Arc<T>>)
+-> (usize, Result<(), NodeError>) {
+    let operation_type = operation.operation_type;
+    for (succeeded, hook) in hooks.iter().enumerate() {
+        let error = match catch_unwind(AssertUnwindSafe(|| hook.before(id, operation, inputs))) {
+            Ok(Ok(())) => continue,
+            Ok(Err(message)) => NodeError::Rejected { id, operation_type, message },
+            Err(panic) => NodeError::Panicked { id, operation_type, message: panic_message(panic) },
+        };
+        return (succeeded, Err(error));
+    }
+    (hooks.len(), Ok(()))
+}
+
+/// Runs `after` on every hook in reverse order.  A panicking hook fails the
+/// node, and the remaining hooks still run.
+pub(crate) fn after<T>(hooks: &[Box<dyn NodeHook<T>>], id: NodeId, operation: &Operation, inputs: &[Arc<T>], result: &mut Result<T, NodeError>) {
+    hooks.iter().rev().for_each(|hook| {
+        if let Err(panic) = catch_unwind(AssertUnwindSafe(|| hook.after(id, operation, inputs, result))) {
+            let message = panic_message(panic);
+            *result = Err(NodeError::Panicked { id, operation_type: operation.operation_type, message });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use crate::computation::Computation;
+    use crate::dag::Dag;
+    use crate::operation::OperationType;
+    use crate::report::NodeStatus;
+
+    /// Caps every input and result at a maximum value
+    struct Clamp(u64);
+
+    impl NodeHook<u64> for Clamp {
+        fn before(&self, _id: NodeId, _operation: &Operation, inputs: &mut Vec<Arc<u64>>) -> Result<(), String> {
+            inputs.iter_mut().for_each(|input| *input = Arc::new((**input).min(self.0)));
+            Ok(())
+        }
+
+        fn after(&self, _id: NodeId, _operation: &Operation, _inputs: &[Arc<u64>], result: &mut Result<u64, NodeError>) {
+            if let Ok(value) = result {
+                *value = (*value).min(self.0);
+            }
+        }
+    }
+
+    /// Rejects any node with more than one input
+    struct SingleInput;
+
+    impl NodeHook<u64> for SingleInput {
+        fn before(&self, _id: NodeId, _operation: &Operation, inputs: &mut Vec<Arc<u64>>) -> Result<(), String> {
+            if inputs.len() > 1 {
+                return Err(format!("{} inputs", inputs.len()));
+            }
+            Ok(())
+        }
+    }
+
+    /// Records the order in which the hooks of every node are called
+    struct Audit(&'static str, Arc<Mutex<Vec<(NodeId, String)>>>);
+
+    impl NodeHook<u64> for Audit {
+        fn before(&self, id: NodeId, _operation: &Operation, _inputs: &mut Vec<Arc<u64>>) -> Result<(), String> {
+            self.1.lock().unwrap().push((id, format!("{} before", self.0)));
+            Ok(())
+        }
+
+        fn after(&self, id: NodeId, _operation: &Operation, _inputs: &[Arc<u64>], result: &mut Result<u64, NodeError>) {
+            let outcome = if result.is_ok() { "ok" } else { "failed" };
+            self.1.lock().unwrap().push((id, format!("{} after {}", self.0, outcome)));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn clamps_inputs_and_results() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Product), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Product), vec![id1]);
+        let mut computation = Computation::new(&dag);
+        computation.add_hook(Clamp(5));
+        let report = computation.process(7u64).await;
+        assert_eq!(report.results, BTreeMap::from([(id2, 5)]));
+    }
+
+    #[tokio::test]
+    pub async fn rejected_node_fails() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id1, id2]);
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut computation = Computation::new(&dag);
+        computation.add_hook(Audit("outer", events.clone()));
+        computation.add_hook(SingleInput);
+        computation.add_hook(Audit("inner", events.clone()));
+        let report = computation.process(1u64).await;
+
+        let error = NodeError::Rejected { id: id3, operation_type: OperationType::Sum, message: "2 inputs".to_owned() };
+        assert_eq!(report.statuses[&id3], NodeStatus::Failed(error));
+        assert_eq!(report.statuses[&id1], NodeStatus::Completed);
+        let events = events.lock().unwrap();
+        let node_events = |node_id| -> Vec<&str> {
+            events.iter().filter(|(id, _)| *id == node_id).map(|(_, event)| event.as_str()).collect()
+        };
+        assert_eq!(node_events(id1), vec!["outer before", "inner before", "inner after ok", "outer after ok"]);
+        // The inner hook never ran, since the node was rejected first
+        assert_eq!(node_events(id3), vec!["outer before", "outer after failed"]);
+    }
+
+    /// Panics in `before` on the given node, and in `after` on any other
+    struct Panics(NodeId);
+
+    impl NodeHook<u64> for Panics {
+        fn before(&self, id: NodeId, _operation: &Operation, _inputs: &mut Vec<Arc<u64>>) -> Result<(), String> {
+            if id == self.0 {
+                panic!("before {}", id);
+            }
+            Ok(())
+        }
+
+        fn after(&self, id: NodeId, _operation: &Operation, _inputs: &[Arc<u64>], _result: &mut Result<u64, NodeError>) {
+            panic!("after {}", id);
+        }
+    }
+
+    #[tokio::test]
+    pub async fn panicking_hook_fails_node() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let events = Arc::new(Mutex::new(vec![]));
+        let mut computation = Computation::new(&dag);
+        computation.add_hook(Audit("outer", events.clone()));
+        computation.add_hook(Panics(id2));
+        let report = computation.process(1u64).await;
+
+        let operation_type = OperationType::Sum;
+        let message = format!("before {}", id2);
+        assert_eq!(report.statuses[&id2], NodeStatus::Failed(NodeError::Panicked { id: id2, operation_type, message }));
+        let message = format!("after {}", id1);
+        assert_eq!(report.statuses[&id1], NodeStatus::Failed(NodeError::Panicked { id: id1, operation_type, message }));
+        assert_eq!(report.statuses[&id3], NodeStatus::Skipped);
+        // The outer hook sees the failure of the inner one
+        assert!(events.lock().unwrap().contains(&(id1, "outer after failed".to_owned())));
+    }
+}
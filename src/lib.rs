@@ -1,4 +1,27 @@
+pub mod cache;
+pub mod cancel;
+#[cfg(feature = "serialize")]
+pub mod checkpoint;
 pub mod dag;
+pub mod debugger;
+#[cfg(feature = "serialize")]
+pub mod distributed;
+pub mod error;
+pub mod fault;
+mod fusion;
+mod hash;
+pub mod hook;
+pub mod lineage;
+pub mod metrics;
 pub mod operation;
 pub mod random;
 pub mod computation;
+pub mod progress;
+pub mod report;
+pub mod retry;
+pub mod schedule;
+pub mod session;
+pub mod simulate;
+#[cfg(feature = "testing")]
+pub mod strategy;
+pub mod watchdog;
@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use crate::dag::NodeId;
+use crate::operation::OperationType;
+
+/// What a node computed, kept when recording lineage
+#[derive(Clone, Debug)]
+pub(crate) struct Record<T> {
+    operation_type: OperationType,
+    inputs: Vec<T>,
+    output: T,
+}
+
+/// Records every node's inputs and output during a single run
+pub(crate) struct LineageRecorder<T> {
+    records: Mutex<HashMap<NodeId, Record<T>>>,
+}
+
+impl<T: Clone> LineageRecorder<T> {
+    pub(crate) fn new() -> Self {
+        Self { records: Mutex::new(HashMap::new()) }
+    }
+
+    pub(crate) fn record(&self, id: NodeId, operation_type: OperationType, inputs: &[Arc<T>], output: &T) {
+        let inputs = inputs.iter().map(|input| T::clone(input)).collect();
+        let record = Record { operation_type, inputs, output: output.clone() };
+        self.records.lock().unwrap().insert(id, record);
+    }
+
+    pub(crate) fn take(&self) -> HashMap<NodeId, Record<T>> {
+        std::mem::take(&mut self.records.lock().unwrap())
+    }
+}
+
+/// What a node of a lineage computed, and from which parents
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct LineageNode<T> {
+    pub operation_type: OperationType,
+    /// Values the operation ran on, the initial value for a start node, or
+    /// none if the value was set before the run
+    pub inputs: Vec<T>,
+    pub value: T,
+    /// Parents that produced the inputs, in the order of the inputs
+    pub parents: Vec<NodeId>,
+}
+
+/// How the value of a node was derived: the operation, inputs, and value of
+/// the node and of every node it was derived from, all the way up to the start
+/// nodes.  Every node appears once, however many paths lead to it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize))]
+pub struct Lineage<T> {
+    /// Node whose value was derived
+    pub id: NodeId,
+    /// The node itself and all of its ancestors, by id
+    pub nodes: BTreeMap<NodeId, LineageNode<T>>,
+}
+
+impl<T: Clone> Lineage<T> {
+    /// Lineage of the given node, if it completed, from the recorded nodes
+    /// and the parents of every node, ordered by id
+    pub(crate) fn build(id: NodeId, records: &HashMap<NodeId, Record<T>>, parents: &HashMap<NodeId, Vec<NodeId>>) -> Option<Self> {
+        records.get(&id)?;
+        let mut nodes = BTreeMap::new();
+        let mut stack = vec![id];
+        while let Some(node_id) = stack.pop() {
+            if nodes.contains_key(&node_id) {
+                continue;
+            }
+            let record = match records.get(&node_id) {
+                Some(record) => record,
+                None => continue,
+            };
+            let node_parents: Vec<NodeId> = if record.inputs.is_empty() {
+                vec![]
+            } else {
+                parents.get(&node_id).into_iter().flatten()
+                    .filter(|parent_id| records.contains_key(parent_id))
+                    .copied()
+                    .collect()
+            };
+            stack.extend(&node_parents);
+            nodes.insert(node_id, LineageNode {
+                operation_type: record.operation_type,
+                inputs: record.inputs.clone(),
+                value: record.output.clone(),
+                parents: node_parents,
+            });
+        }
+        Some(Lineage { id, nodes })
+    }
+}
+
+impl<T> Lineage<T> {
+    /// The node whose value was derived
+    pub fn node(&self) -> &LineageNode<T> {
+        &self.nodes[&self.id]
+    }
+
+    /// Every node the value was derived from, leaving out the node itself
+    pub fn ancestors(&self) -> BTreeSet<NodeId> {
+        self.nodes.keys().filter(|id| **id != self.id).copied().collect()
+    }
+
+    /// Graph of the lineage in dot format, with the operation, inputs, and
+    /// value of every node, and the value passed along every edge
+    pub fn dot(&self) -> String
+    where T: Debug {
+        let mut dot = "digraph {\n".to_owned();
+        self.nodes.iter().for_each(|(id, node)| {
+            let label = format!("{}: {:?}\\ninputs {:?}\\n= {:?}", id, node.operation_type, node.inputs, node.value);
+            dot += &format!("  {} [label=\"{}\"];\n", id, label.replace('"', "\\\""));
+        });
+        self.nodes.iter().for_each(|(id, node)| {
+            node.parents.iter().for_each(|parent_id| {
+                let value = format!("{:?}", self.nodes[parent_id].value).replace('"', "\\\"");
+                dot += &format!("  {} -> {} [label=\"{}\"];\n", parent_id, id, value);
+            });
+        });
+        dot += "}";
+        dot
+    }
+
+    /// The whole lineage graph as JSON, every node once
+    #[cfg(feature = "serialize")]
+    pub fn to_json(&self) -> serde_json::Result<String>
+    where T: serde::Serialize {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::computation::Computation;
+    use crate::dag::Dag;
+    use crate::operation::Operation;
+
+    /// Diamond of sums feeding a product, and an unrelated node
+    fn dag() -> (Dag, Vec<NodeId>) {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id4 = dag.add_node(Operation::new(OperationType::Product), vec![id2, id3]);
+        let id5 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        (dag, vec![id1, id2, id3, id4, id5])
+    }
+
+    #[tokio::test]
+    pub async fn lineage_of_sink() {
+        let (dag, ids) = dag();
+        let mut computation = Computation::new(&dag);
+        computation.record_lineage();
+        let report = computation.process(3u64).await;
+
+        let lineage = report.lineage(ids[3]).unwrap();
+        let node = lineage.node();
+        assert_eq!(node.operation_type, OperationType::Product);
+        assert_eq!(node.inputs, vec![3, 3]);
+        assert_eq!(node.value, 9);
+        assert_eq!(node.parents, vec![ids[1], ids[2]]);
+        assert_eq!(lineage.nodes[&ids[1]].parents, vec![ids[0]]);
+        assert_eq!(lineage.nodes[&ids[0]].inputs, vec![3]);
+        assert_eq!(lineage.ancestors(), vec![ids[0], ids[1], ids[2]].into_iter().collect());
+        assert!(report.lineage(ids[4]).unwrap().ancestors().is_empty());
+    }
+
+    #[tokio::test]
+    pub async fn nothing_recorded_by_default() {
+        let (dag, ids) = dag();
+        let report = Computation::new(&dag).process(3u64).await;
+        assert_eq!(report.lineage(ids[3]), None);
+    }
+
+    #[tokio::test]
+    pub async fn annotated_dot() {
+        let (dag, ids) = dag();
+        let mut computation = Computation::new(&dag);
+        computation.record_lineage();
+        let report = computation.process(3u64).await;
+
+        let dot = report.lineage(ids[3]).unwrap().dot();
+        assert!(dot.starts_with("digraph {\n"));
+        assert!(dot.contains(&format!("  {} [label=\"{}: Product\\ninputs [3, 3]\\n= 9\"];\n", ids[3], ids[3])));
+        assert!(dot.contains(&format!("  {} -> {} [label=\"3\"];\n", ids[0], ids[1])));
+        // The shared parent only appears once, and so does every edge
+        assert_eq!(dot.matches(&format!("  {} [label", ids[0])).count(), 1);
+        assert_eq!(dot.matches(&format!("  {} -> {} ", ids[0], ids[1])).count(), 1);
+        assert!(!dot.contains(&format!("  {} [label", ids[4])));
+    }
+
+    #[cfg(feature = "serialize")]
+    #[tokio::test]
+    pub async fn json_export() {
+        let (dag, ids) = dag();
+        let mut computation = Computation::new(&dag);
+        computation.record_lineage();
+        let report = computation.process(3u64).await;
+
+        let json: serde_json::Value = serde_json::from_str(&report.lineage(ids[1]).unwrap().to_json().unwrap()).unwrap();
+        let node = &json["nodes"][ids[1].to_string()];
+        assert_eq!(json["id"], ids[1]);
+        assert_eq!(node["operation_type"], "Sum");
+        assert_eq!(node["value"], 3);
+        assert_eq!(node["parents"][0], ids[0]);
+        assert_eq!(json["nodes"][ids[0].to_string()]["inputs"][0], 3);
+    }
+
+    #[tokio::test]
+    pub async fn shared_ancestors_kept_once() {
+        // Stacked diamonds, with twice as many paths to the top at every level
+        let mut dag: Dag = Default::default();
+        let mut id = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        for _ in 0..40 {
+            let left = dag.add_node(Operation::new(OperationType::Sum), vec![id]);
+            let right = dag.add_node(Operation::new(OperationType::Sum), vec![id]);
+            id = dag.add_node(Operation::new(OperationType::Default), vec![left, right]);
+        }
+        let mut computation = Computation::new(&dag);
+        computation.record_lineage();
+        let report = computation.process(1u64).await;
+
+        let lineage = report.lineage(id).unwrap();
+        assert_eq!(lineage.nodes.len(), 121);
+        assert_eq!(lineage.ancestors().len(), 120);
+        assert_eq!(lineage.dot().matches(" -> ").count(), 160);
+    }
+}
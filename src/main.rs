@@ -1,18 +1,111 @@
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
 use structopt::StructOpt;
-use rand::{Rng, thread_rng};
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+use tokio::net::TcpListener;
+use tokio::time::{Instant, delay_for};
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
 
-use computation_dag_async::computation::Computation;
-use computation_dag_async::dag::Dag;
+use computation_dag_async::cancel::CancellationToken;
+use computation_dag_async::computation::{Computation, ExecutionPolicy};
+use computation_dag_async::dag::{Dag, NodeId};
+use computation_dag_async::debugger::{Debugger, Pause};
+use computation_dag_async::fault::{Fault, FaultInjector, InvalidProbability};
+use computation_dag_async::metrics::Metrics;
+#[cfg(feature = "serialize")]
+use computation_dag_async::distributed::{Address, Coordinator, serve};
 use computation_dag_async::operation::{Operation, OperationType};
+use computation_dag_async::progress::{ProgressEvent, progress_line};
 use computation_dag_async::random::DagDistribution;
+use computation_dag_async::report::Report;
+use computation_dag_async::retry::{Backoff, RetryPolicy};
+use computation_dag_async::schedule::{SchedulingPolicy, compare_scheduling};
+use computation_dag_async::simulate::simulate;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Print,
+    Execute,
+    Debug,
+    Simulate,
+    #[cfg(feature = "serialize")]
+    Worker,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "print" => Ok(Mode::Print),
+            "execute" => Ok(Mode::Execute),
+            "debug" => Ok(Mode::Debug),
+            "simulate" => Ok(Mode::Simulate),
+            #[cfg(feature = "serialize")]
+            "worker" => Ok(Mode::Worker),
+            _ => Err(format!("unknown mode {:?}, expected \"print\", \"execute\", \"debug\", \"simulate\", \
+                or, with the `serialize` feature, \"worker\"", mode)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LogFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "human" => Ok(LogFormat::Human),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {:?}, expected \"human\" or \"json\"", format)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LineageFormat {
+    Dot,
+    #[cfg(feature = "serialize")]
+    Json,
+}
+
+impl FromStr for LineageFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "dot" => Ok(LineageFormat::Dot),
+            #[cfg(feature = "serialize")]
+            "json" => Ok(LineageFormat::Json),
+            _ => Err(format!("unknown lineage format {:?}, expected \"dot\" or, with the `serialize` \
+                feature, \"json\"", format)),
+        }
+    }
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "rust-dag", about = "Create directed acyclic graphs with mathematic operations.")]
 struct Opt {
-    /// Activate debug mode, which for printing the generated DAG and execution
+    /// Activate debug mode, which for printing the generated DAG and logging
+    /// the execution of every node
     #[structopt(short, long)]
     debug: bool,
 
+    /// Format of the logs written to stderr, either "human" or "json"
+    #[structopt(long, default_value = "human")]
+    log_format: LogFormat,
+
     /// Set minimum nodes in random DAG
     #[structopt(short = "n", long, default_value = "10")]
     min_nodes: u64,
@@ -25,9 +118,9 @@ struct Opt {
     #[structopt(short = "p", long, default_value = "40")]
     edge_percentage: u32,
 
-    /// Running mode, either "print" or "execute"
+    /// Running mode, either "print", "execute", "debug", "simulate", or "worker"
     #[structopt(short = "m", long, default_value = "print")]
-    mode: String,
+    mode: Mode,
 
     /// Force DAG functions to be "delay", will always result in 0 as a response,
     /// but will show simultaneous execution
@@ -38,41 +131,497 @@ struct Opt {
     /// but allows for tests with huge trees.
     #[structopt(long)]
     default: bool,
+
+    /// Abort execution after this many seconds, skipping any unfinished nodes
+    #[structopt(long)]
+    deadline: Option<u64>,
+
+    /// Abort execution once no node has completed for this many seconds,
+    /// showing which nodes are stuck
+    #[structopt(long)]
+    watchdog: Option<u64>,
+
+    /// Attempt failing nodes up to this many times, with exponential backoff
+    #[structopt(long, default_value = "1")]
+    attempts: u32,
+
+    /// Stop the whole computation as soon as one node fails
+    #[structopt(long)]
+    fail_fast: bool,
+
+    /// Seed for generating the random DAG, to get the same DAG on every run
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Only compute the given nodes and their ancestors, can be repeated
+    #[structopt(long = "target")]
+    targets: Vec<u64>,
+
+    /// Run at most this many operations at once, or the number of workers to
+    /// simulate, 4 by default
+    #[structopt(long)]
+    concurrency: Option<NonZeroUsize>,
+
+    /// When the concurrency is limited, run nodes on the longest remaining path first
+    #[structopt(long)]
+    critical_path: bool,
+
+    /// Run the DAG with both FIFO and critical path scheduling, and report the
+    /// makespan of each, using `--concurrency` or 2 running nodes at once
+    #[structopt(long)]
+    compare_scheduling: bool,
+
+    /// Fault injected in the attempts of chosen nodes, either "fail", "hang",
+    /// or "delay:<milliseconds>"
+    #[structopt(long, default_value = "fail")]
+    fault: Fault,
+
+    /// Inject the fault in every attempt of any node with this probability,
+    /// between 0 and 1
+    #[structopt(long, parse(try_from_str = parse_probability))]
+    fault_probability: Option<f64>,
+
+    /// Seed choosing the attempts with a fault, `--seed` or 0 by default
+    #[structopt(long)]
+    fault_seed: Option<u64>,
+
+    /// Inject the fault in every attempt of this node, can be repeated
+    #[structopt(long = "fault-node")]
+    fault_nodes: Vec<u64>,
+
+    /// Also report the value of this node, even if it is not a sink, can be repeated
+    #[structopt(long = "watch")]
+    watched: Vec<u64>,
+
+    /// Print how the value of this node was derived once executed
+    #[structopt(long)]
+    lineage: Option<u64>,
+
+    /// Format of the printed lineage, either "dot" or, with the `serialize`
+    /// feature, "json"
+    #[structopt(long, default_value = "dot")]
+    lineage_format: LineageFormat,
+
+    /// Show a live progress bar on stderr while executing
+    #[structopt(long)]
+    progress: bool,
+
+    /// Write runtime metrics in the Prometheus text format to this file, every
+    /// second while executing and once done
+    #[structopt(long)]
+    metrics_file: Option<std::path::PathBuf>,
+
+    /// Serve runtime metrics in the Prometheus text format over HTTP at this
+    /// address while executing, for example `127.0.0.1:9100`
+    #[structopt(long)]
+    metrics_listen: Option<String>,
+
+    /// Directory caching node results between runs
+    #[cfg(feature = "serialize")]
+    #[structopt(long)]
+    cache_dir: Option<std::path::PathBuf>,
+
+    /// File recording completed nodes, resumed if it already exists.  Use with
+    /// `--seed` to execute the same DAG again.
+    #[cfg(feature = "serialize")]
+    #[structopt(long)]
+    checkpoint: Option<std::path::PathBuf>,
+
+    /// Address to listen on in worker mode, `host:port` or `unix:<path>`
+    #[cfg(feature = "serialize")]
+    #[structopt(long, default_value = "127.0.0.1:7000")]
+    listen: Address,
+
+    /// Execute on the worker at this address instead of locally, can be
+    /// repeated.  Only `--deadline`, `--watch` and `--dispatch-timeout` apply
+    /// to workers.
+    #[cfg(feature = "serialize")]
+    #[structopt(long = "worker", conflicts_with_all = &[
+        "watchdog", "attempts", "fail-fast", "targets", "concurrency", "critical-path", "compare-scheduling",
+        "fault", "fault-probability", "fault-seed", "fault-nodes", "lineage", "progress", "metrics-file",
+        "metrics-listen", "cache-dir", "checkpoint",
+    ])]
+    workers: Vec<Address>,
+
+    /// With `--worker`, send a node to another worker once its worker has not
+    /// answered for this many seconds
+    #[cfg(feature = "serialize")]
+    #[structopt(long, requires = "workers")]
+    dispatch_timeout: Option<u64>,
+}
+
+/// Parses a probability, rejecting anything outside of 0 to 1
+fn parse_probability(probability: &str) -> Result<f64, String> {
+    let probability: f64 = probability.parse().map_err(|_| format!("invalid probability {:?}", probability))?;
+    if !(0.0..=1.0).contains(&probability) {
+        return Err(InvalidProbability(probability).to_string());
+    }
+    Ok(probability)
+}
+
+fn print_report(report: &Report<u128>) {
+    if report.cancelled {
+        println!("Cancelled, completed nodes: {:?}", report.completed());
+    }
+    if let Some(stall) = &report.stall {
+        println!("Aborted, {}", stall);
+    }
+    println!("Results: {:?}", report.results);
+    let mut values: Vec<(&NodeId, &u128)> = report.values.iter().collect();
+    if !values.is_empty() {
+        values.sort_unstable();
+        println!("Values: {:?}", values);
+    }
+    if report.cache.hits + report.cache.misses > 0 {
+        println!("Cache: {} hits, {} misses", report.cache.hits, report.cache.misses);
+    }
+    for error in report.errors() {
+        println!("Error: {}", error);
+    }
+    let pruned = report.pruned();
+    if !pruned.is_empty() {
+        println!("Pruned {} nodes not needed by the targets", pruned.len());
+    }
+    let skipped = report.skipped();
+    if !skipped.is_empty() {
+        println!("Skipped: {:?}", skipped);
+    }
+}
+
+/// Logs to stderr, every node with its duration when debugging, otherwise
+/// only warnings and errors
+fn init_logging(opt: &Opt) {
+    let level = if opt.debug { Level::DEBUG } else { Level::WARN };
+    let builder = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_thread_ids(true)
+        .with_span_events(FmtSpan::CLOSE);
+    match opt.log_format {
+        LogFormat::Human => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
+
+/// Redraws the progress bar on stderr at most every 100 milliseconds, until
+/// the computation drops its end of the channel
+async fn show_progress(mut events: tokio::sync::mpsc::UnboundedReceiver<ProgressEvent>) {
+    let start = Instant::now();
+    let mut drawn = start;
+    while let Some(event) = events.recv().await {
+        if let ProgressEvent::Finished { remaining, total, .. } = event {
+            // Always draw the last node, so that the bar ends full
+            if drawn.elapsed() >= Duration::from_millis(100) || remaining == 0 {
+                eprint!("\r{}", progress_line(total - remaining, total, start.elapsed(), 40));
+                drawn = Instant::now();
+            }
+        }
+    }
+    eprintln!();
+}
+
+const DEBUG_HELP: &str = "\
+Commands:
+  step, s                    run this node, then pause at the next one
+  continue, c                run until the next breakpoint
+  break, b <node|operation>  pause at a node id or an operation type
+  delete, d <node|operation> remove a breakpoint
+  print, p <node>            show the inputs and output of a completed node
+  set <value>                use this value instead of running this node
+  help, h                    show this help";
+
+fn parse_operation_type(name: &str) -> Option<OperationType> {
+    match name.to_lowercase().as_str() {
+        "default" => Some(OperationType::Default),
+        "delay" => Some(OperationType::Delay),
+        "sum" => Some(OperationType::Sum),
+        "product" => Some(OperationType::Product),
+        _ => None,
+    }
+}
+
+/// Reads commands about the paused node until one of them lets it run.  The
+/// end of the input resumes the computation.
+async fn debug_prompt(mut pause: Pause<u128>, debugger: &Debugger<u128>, lines: &mut Lines<BufReader<Stdin>>) {
+    println!("Paused at node {} ({:?}) with inputs {:?}", pause.id, pause.operation_type, pause.inputs);
+    loop {
+        print!("(debug) ");
+        let _ = std::io::Write::flush(&mut std::io::stdout());
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            _ => return pause.resume(),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["step"] | ["s"] => return pause.step(),
+            ["continue"] | ["c"] => return pause.resume(),
+            ["break", target] | ["b", target] => match (target.parse::<NodeId>(), parse_operation_type(target)) {
+                (Ok(id), _) => debugger.break_at(id),
+                (_, Some(operation_type)) => debugger.break_on(operation_type),
+                _ => println!("Unknown node or operation {}", target),
+            },
+            ["delete", target] | ["d", target] => match (target.parse::<NodeId>(), parse_operation_type(target)) {
+                (Ok(id), _) => debugger.remove_breakpoint(id),
+                (_, Some(operation_type)) => debugger.remove_operation_breakpoint(operation_type),
+                _ => println!("Unknown node or operation {}", target),
+            },
+            ["print", id] | ["p", id] => match id.parse::<NodeId>().ok().and_then(|id| debugger.inspect(id)) {
+                Some(values) => println!("Inputs {:?}, output {}", values.inputs, values.output),
+                None => println!("Node {} has not completed", id),
+            },
+            ["set", value] => match value.parse::<u128>() {
+                Ok(value) => {
+                    pause.set_value(value);
+                    println!("Node {} will return {}", pause.id, value);
+                },
+                Err(_) => println!("Invalid value {}", value),
+            },
+            [] | ["help"] | ["h"] => println!("{}", DEBUG_HELP),
+            _ => println!("Unknown command, type help for the list of commands"),
+        }
+    }
 }
 
 #[tokio::main(core_threads = 8)]
 async fn main() {
     let opt = Opt::from_args();
+    init_logging(&opt);
     let min_nodes = opt.min_nodes;
     let max_nodes = opt.max_nodes;
     let edge_percentage = opt.edge_percentage;
     let default_operation = if opt.delay {
         let operation_type = OperationType::Delay;
-        Some(Operation { operation_type })
+        Some(Operation::new(operation_type))
     } else if opt.default {
         let operation_type = OperationType::Default;
-        Some(Operation { operation_type })
+        Some(Operation::new(operation_type))
     } else {
         None
     };
     let distribution = DagDistribution {
         min_nodes, max_nodes, edge_percentage, default_operation,
     };
-    let mut rng = thread_rng();
-    let dag: Dag = rng.sample(distribution);
-    match opt.mode.as_str() {
-        "print" => {
+    let dag: Dag = match opt.seed {
+        Some(seed) => StdRng::seed_from_u64(seed).sample(distribution),
+        None => thread_rng().sample(distribution),
+    };
+    match opt.mode {
+        Mode::Print => {
+            println!("{}", dag.dot());
+        },
+        Mode::Debug => {
             println!("{}", dag.dot());
+            println!("{}", DEBUG_HELP);
+            let debugger = Arc::new(Debugger::new(true));
+            let mut computation = Computation::new(&dag);
+            let mut pauses = computation.set_debugger(debugger.clone());
+            let run = tokio::spawn(computation.process(1u128));
+            let mut lines = BufReader::new(tokio::io::stdin()).lines();
+            while let Some(pause) = pauses.recv().await {
+                debug_prompt(pause, &debugger, &mut lines).await;
+            }
+            print_report(&run.await.expect("Computation failed"));
         },
-        "execute" => {
+        Mode::Simulate => {
+            let workers = opt.concurrency.unwrap_or_else(|| NonZeroUsize::new(4).unwrap());
+            let policy = if opt.critical_path {
+                SchedulingPolicy::CriticalPath(Default::default())
+            } else {
+                SchedulingPolicy::Fifo
+            };
+            let simulation = simulate(&dag, &Default::default(), workers, &policy);
+            println!("node\tworker\tstart\tfinish");
+            for id in simulation.order() {
+                let node = simulation.nodes[&id];
+                println!("{}\t{}\t{:?}\t{:?}", id, node.worker, node.start, node.finish);
+            }
+            println!("Makespan: {:?}", simulation.makespan);
+            println!("Sequential: {:?}", simulation.sequential);
+            println!("Speedup: {:.2}", simulation.speedup());
+            println!("Utilization: {:.1}%", simulation.utilization() * 100.0);
+        },
+        Mode::Execute => {
             if opt.debug {
                 println!("{}", dag.dot());
             }
-            let computation = Computation::new(&dag, opt.debug);
             let initial: u128 = 1;
-            let results = computation.process(initial).await;
-            println!("Results: {:?}", results);
+            #[cfg(feature = "serialize")]
+            {
+                if !opt.workers.is_empty() {
+                    let mut coordinator = Coordinator::new(&dag, opt.workers.clone());
+                    if let Some(deadline) = opt.deadline {
+                        coordinator.set_deadline(Duration::from_secs(deadline));
+                    }
+                    if let Some(limit) = opt.dispatch_timeout {
+                        coordinator.set_dispatch_timeout(Duration::from_secs(limit));
+                    }
+                    if let Err(error) = coordinator.watch(&opt.watched) {
+                        eprintln!("Invalid watched node: {}", error);
+                        std::process::exit(1);
+                    }
+                    let token = coordinator.cancellation_token();
+                    tokio::spawn(async move {
+                        if tokio::signal::ctrl_c().await.is_ok() {
+                            token.cancel();
+                        }
+                    });
+                    match coordinator.process(initial).await {
+                        Ok(report) => print_report(&report),
+                        Err(error) => {
+                            eprintln!("Error running on workers: {}", error);
+                            std::process::exit(1);
+                        },
+                    }
+                    return;
+                }
+            }
+            if opt.compare_scheduling {
+                let concurrency = opt.concurrency.unwrap_or_else(|| NonZeroUsize::new(2).unwrap());
+                let comparison = compare_scheduling(&dag, initial, concurrency, Default::default()).await;
+                println!("Makespan with {} nodes at once:", concurrency);
+                println!("  FIFO: {:?}", comparison.fifo);
+                println!("  Critical path: {:?}", comparison.critical_path);
+                return;
+            }
+            let mut computation = Computation::new(&dag);
+            let metrics = Arc::new(Metrics::new());
+            let exporting = CancellationToken::new();
+            if opt.metrics_file.is_some() || opt.metrics_listen.is_some() {
+                computation.set_metrics(metrics.clone());
+            }
+            if let Some(path) = opt.metrics_file.clone() {
+                let metrics = metrics.clone();
+                let token = exporting.clone();
+                tokio::spawn(async move {
+                    while !token.is_cancelled() {
+                        if let Err(error) = metrics.write_to(&path) {
+                            tracing::error!(%error, "error writing metrics");
+                        }
+                        tokio::select! {
+                            _ = delay_for(Duration::from_secs(1)) => {},
+                            _ = token.cancelled() => {},
+                        }
+                    }
+                });
+            }
+            if let Some(address) = &opt.metrics_listen {
+                let listener = match TcpListener::bind(address).await {
+                    Ok(listener) => listener,
+                    Err(error) => {
+                        eprintln!("Error listening for metrics on {}: {}", address, error);
+                        std::process::exit(1);
+                    },
+                };
+                tokio::spawn(computation_dag_async::metrics::serve(metrics.clone(), listener, exporting.clone()));
+            }
+            let progress = if opt.progress {
+                Some(tokio::spawn(show_progress(computation.progress_events())))
+            } else {
+                None
+            };
+            if let Some(concurrency) = opt.concurrency {
+                computation.set_concurrency(concurrency);
+            }
+            if opt.critical_path {
+                computation.set_scheduling_policy(SchedulingPolicy::CriticalPath(Default::default()));
+            }
+            if let Some(deadline) = opt.deadline {
+                computation.set_deadline(Duration::from_secs(deadline));
+            }
+            if let Some(idle) = opt.watchdog {
+                computation.set_watchdog(Duration::from_secs(idle));
+            }
+            #[cfg(feature = "serialize")]
+            {
+                if let Some(cache_dir) = &opt.cache_dir {
+                    let cache = computation_dag_async::cache::DiskCache::new(cache_dir).expect("Error creating cache");
+                    computation.set_cache(Arc::new(cache));
+                }
+                if let Some(path) = &opt.checkpoint {
+                    use computation_dag_async::checkpoint::Checkpoint;
+                    // Only the initial value is sent, no start node has an input of its own
+                    let inputs = std::collections::HashMap::new();
+                    let (checkpoint, completed) = if path.exists() {
+                        let resumed = Checkpoint::resume(path, &dag, &initial, &inputs).expect("Error resuming checkpoint");
+                        println!("Resuming with {} completed nodes", resumed.1.len());
+                        resumed
+                    } else {
+                        (Checkpoint::create(path, &dag, &initial, &inputs).expect("Error creating checkpoint"), Default::default())
+                    };
+                    computation.set_checkpoint(checkpoint, completed);
+                }
+            }
+            if opt.fault_probability.is_some() || !opt.fault_nodes.is_empty() {
+                let seed = opt.fault_seed.or(opt.seed).unwrap_or_default();
+                let probability = opt.fault_probability.unwrap_or_default();
+                // The probability was checked while parsing the arguments
+                let mut injector = FaultInjector::random(seed, probability, opt.fault).unwrap();
+                opt.fault_nodes.iter().for_each(|id| injector.inject(*id, opt.fault));
+                computation.set_fault_injector(injector);
+            }
+            if !opt.targets.is_empty() {
+                if let Err(error) = computation.set_targets(opt.targets.clone()) {
+                    eprintln!("Invalid target: {}", error);
+                    std::process::exit(1);
+                }
+            }
+            if opt.fail_fast {
+                computation.set_execution_policy(ExecutionPolicy::FailFast);
+            }
+            if !opt.watched.is_empty() {
+                if let Err(error) = computation.watch(&opt.watched) {
+                    eprintln!("Invalid watched node: {}", error);
+                    std::process::exit(1);
+                }
+            }
+            if opt.lineage.is_some() {
+                computation.record_lineage();
+            }
+            let backoff = Backoff::Exponential { initial: Duration::from_millis(100), max: Duration::from_secs(5) };
+            for operation_type in &[OperationType::Default, OperationType::Delay, OperationType::Sum, OperationType::Product] {
+                computation.set_operation_retry_policy(*operation_type, RetryPolicy::new(opt.attempts, backoff.clone()));
+            }
+            let token = computation.cancellation_token();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    token.cancel();
+                }
+            });
+            let report = computation.process(initial).await;
+            if let Some(progress) = progress {
+                progress.await.expect("Progress bar failed");
+            }
+            exporting.cancel();
+            if let Some(path) = &opt.metrics_file {
+                metrics.write_to(path).expect("Error writing metrics");
+            }
+            print_report(&report);
+            if let Some(id) = opt.lineage {
+                match report.lineage(id) {
+                    Some(lineage) => match opt.lineage_format {
+                        LineageFormat::Dot => println!("{}", lineage.dot()),
+                        #[cfg(feature = "serialize")]
+                        LineageFormat::Json => println!("{}", lineage.to_json().expect("Error serializing lineage")),
+                    },
+                    None => println!("Node {} did not complete, it has no lineage", id),
+                }
+            }
+        },
+        #[cfg(feature = "serialize")]
+        Mode::Worker => {
+            let address = &opt.listen;
+            let token = CancellationToken::new();
+            let stop = token.clone();
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    stop.cancel();
+                }
+            });
+            println!("Worker listening on {}", address);
+            if let Err(error) = serve::<u128>(address, token).await {
+                eprintln!("Error running worker on {}: {}", address, error);
+                std::process::exit(1);
+            }
         },
-        _ => panic!("Unknown mode"),
     }
 }
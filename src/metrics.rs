@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::warn;
+
+use crate::cancel::CancellationToken;
+use crate::operation::OperationType;
+
+/// Upper bounds of the histogram buckets in seconds, the Prometheus defaults
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Distribution of durations, with a count per bucket
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// Observations falling in each bucket, not cumulative
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(index) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index] += 1;
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, output: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        BUCKETS.iter().zip(&self.buckets).for_each(|(bound, count)| {
+            cumulative += count;
+            let _ = writeln!(output, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative);
+        });
+        let _ = writeln!(output, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, self.count);
+        let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        let _ = writeln!(output, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(output, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct OperationMetrics {
+    executed: u64,
+    failures: u64,
+    durations: Histogram,
+}
+
+#[derive(Default)]
+struct MetricsState {
+    operations: HashMap<OperationType, OperationMetrics>,
+    queue_wait: Histogram,
+    in_flight: u64,
+}
+
+/// Counters and histograms fed by every `Computation` it is given to, which
+/// can be shared between runs and exported in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Nodes running the given operation type that ran to completion, failed or not
+    pub fn executed(&self, operation_type: OperationType) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.operations.get(&operation_type).map_or(0, |metrics| metrics.executed)
+    }
+
+    /// Nodes running the given operation type that failed, after any retries
+    pub fn failures(&self, operation_type: OperationType) -> u64 {
+        let state = self.state.lock().unwrap();
+        state.operations.get(&operation_type).map_or(0, |metrics| metrics.failures)
+    }
+
+    /// Nodes currently running their operation
+    pub fn in_flight(&self) -> u64 {
+        self.state.lock().unwrap().in_flight
+    }
+
+    /// Counts a node as running, after waiting `queue_wait` between receiving
+    /// its inputs and starting, until the returned guard is dropped
+    pub(crate) fn start_node(&self, queue_wait: Duration) -> InFlight<'_> {
+        let mut state = self.state.lock().unwrap();
+        state.queue_wait.observe(queue_wait);
+        state.in_flight += 1;
+        InFlight { metrics: self }
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut operations: Vec<(String, &OperationMetrics)> = state.operations.iter()
+            .map(|(operation_type, metrics)| (format!("{:?}", operation_type), metrics))
+            .collect();
+        operations.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut output = String::new();
+        output.push_str("# HELP dag_nodes_executed_total Nodes whose operation ran to completion, failed or not.\n");
+        output.push_str("# TYPE dag_nodes_executed_total counter\n");
+        operations.iter().for_each(|(name, metrics)| {
+            let _ = writeln!(output, "dag_nodes_executed_total{{operation=\"{}\"}} {}", name, metrics.executed);
+        });
+        output.push_str("# HELP dag_node_failures_total Nodes that failed after every retry.\n");
+        output.push_str("# TYPE dag_node_failures_total counter\n");
+        operations.iter().for_each(|(name, metrics)| {
+            let _ = writeln!(output, "dag_node_failures_total{{operation=\"{}\"}} {}", name, metrics.failures);
+        });
+        output.push_str("# HELP dag_node_duration_seconds Time spent running the operation of a node, retries included.\n");
+        output.push_str("# TYPE dag_node_duration_seconds histogram\n");
+        operations.iter().for_each(|(name, metrics)| {
+            let labels = format!("operation=\"{}\"", name);
+            metrics.durations.render(&mut output, "dag_node_duration_seconds", &labels);
+        });
+        output.push_str("# HELP dag_queue_wait_seconds Time between a node receiving its inputs and starting to run.\n");
+        output.push_str("# TYPE dag_queue_wait_seconds histogram\n");
+        state.queue_wait.render(&mut output, "dag_queue_wait_seconds", "");
+        output.push_str("# HELP dag_tasks_in_flight Nodes currently running their operation.\n");
+        output.push_str("# TYPE dag_tasks_in_flight gauge\n");
+        let _ = writeln!(output, "dag_tasks_in_flight {}", state.in_flight);
+        output
+    }
+
+    /// Writes every metric to the given file, replacing it at once so that a
+    /// reader never sees a partial file
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, self.render())?;
+        fs::rename(&temporary, path)
+    }
+}
+
+/// Node counted as running, until dropped or finished
+pub(crate) struct InFlight<'a> {
+    metrics: &'a Metrics,
+}
+
+impl InFlight<'_> {
+    /// Records a node that ran for `duration`, and whether it failed
+    pub(crate) fn finish(self, operation_type: OperationType, duration: Duration, failed: bool) {
+        let mut state = self.metrics.state.lock().unwrap();
+        let metrics = state.operations.entry(operation_type).or_default();
+        metrics.executed += 1;
+        if failed {
+            metrics.failures += 1;
+        }
+        metrics.durations.observe(duration);
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.metrics.state.lock().unwrap().in_flight -= 1;
+    }
+}
+
+/// Serves the metrics over HTTP on an already bound listener, whatever the
+/// requested path, until the token is cancelled
+pub async fn serve(metrics: Arc<Metrics>, mut listener: TcpListener, token: CancellationToken) -> io::Result<()> {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let stream = accepted?.0;
+                let metrics = metrics.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = respond(stream, &metrics).await {
+                        warn!(%error, "error serving metrics");
+                    }
+                });
+            },
+            _ = token.cancelled() => return Ok(()),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    // The request itself does not matter, only read enough of it to answer
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request).await?;
+    let body = metrics.render();
+    let header = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len(),
+    );
+    stream.write_all(header.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown(std::net::Shutdown::Write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::computation::Computation;
+    use crate::dag::Dag;
+    use crate::operation::Operation;
+
+    #[tokio::test]
+    pub async fn counts_executed_nodes() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        dag.add_node(Operation::new(OperationType::Product), vec![id1, id2]);
+        let metrics = Arc::new(Metrics::new());
+        let mut computation = Computation::new(&dag);
+        computation.set_metrics(metrics.clone());
+        computation.process(2u64).await;
+
+        assert_eq!(metrics.executed(OperationType::Sum), 2);
+        assert_eq!(metrics.executed(OperationType::Product), 1);
+        assert_eq!(metrics.failures(OperationType::Sum), 0);
+        assert_eq!(metrics.in_flight(), 0);
+        let text = metrics.render();
+        assert!(text.contains("dag_nodes_executed_total{operation=\"Sum\"} 2\n"));
+        assert!(text.contains("dag_node_duration_seconds_count{operation=\"Product\"} 1\n"));
+        assert!(text.contains("dag_queue_wait_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("dag_tasks_in_flight 0\n"));
+    }
+
+    #[test]
+    pub fn cumulative_buckets() {
+        let mut histogram = Histogram::default();
+        histogram.observe(Duration::from_millis(1));
+        histogram.observe(Duration::from_millis(200));
+        histogram.observe(Duration::from_secs(60));
+        let mut output = String::new();
+        histogram.render(&mut output, "wait", "");
+        assert!(output.contains("wait_bucket{le=\"0.005\"} 1\n"));
+        assert!(output.contains("wait_bucket{le=\"0.25\"} 2\n"));
+        assert!(output.contains("wait_bucket{le=\"10\"} 2\n"));
+        assert!(output.contains("wait_bucket{le=\"+Inf\"} 3\n"));
+        assert!(output.contains("wait_count 3\n"));
+    }
+
+    #[tokio::test]
+    pub async fn serves_over_http() {
+        let metrics = Arc::new(Metrics::new());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let token = CancellationToken::new();
+        let server = tokio::spawn(serve(metrics, listener, token.clone()));
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("dag_tasks_in_flight 0\n"));
+
+        token.cancel();
+        server.await.unwrap().unwrap();
+    }
+}
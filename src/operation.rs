@@ -1,35 +1,41 @@
+use std::borrow::Borrow;
 use std::iter::{Product, Sum};
 use std::time::Duration;
 use std::fmt::Debug;
 
 use tokio::time::delay_for;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub enum OperationType {
+    #[default]
     Default,
     Delay,
     Sum,
     Product,
 }
 
-impl Default for OperationType {
-    fn default() -> Self {
-        OperationType::Default
-    }
-}
-
 /// Convenience trait to avoid retyping all of the traits every time
 pub trait Operable<'a, T: 'static>: Debug + Default + Clone + Product<&'a T> + Sum<&'a T> {}
 impl<'a, T: Debug + Default + Clone + Product<&'a T> + Sum<&'a T> + 'static> Operable<'a, T> for T {}
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Operation {
     pub operation_type: OperationType,
+    /// Maximum time the operation may run before the node is considered timed out
+    pub timeout: Option<Duration>,
 }
 
 impl Operation {
-    pub async fn process<T>(&self, values: &Vec<T>) -> T
-    where for<'a> T: Operable<'a, T> + 'static {
+    pub fn new(operation_type: OperationType) -> Self {
+        let timeout = None;
+        Operation { operation_type, timeout }
+    }
+
+    /// Runs the operation on borrowed values, either the values themselves or
+    /// shared references to them such as `Arc<T>`
+    pub async fn process<T, V>(&self, values: &[V]) -> T
+    where for<'a> T: Operable<'a, T> + 'static, V: Borrow<T> {
         match &self.operation_type {
             OperationType::Default => default(values).await,
             OperationType::Delay => delay(values).await,
@@ -37,33 +43,51 @@ impl Operation {
             OperationType::Product => product(values).await,
         }
     }
+
+    /// Computes the same value as `process`, synchronously and without any
+    /// delay, for reference evaluations
+    pub fn evaluate<T, V>(&self, values: &[V]) -> T
+    where for<'a> T: Operable<'a, T> + 'static, V: Borrow<T> {
+        evaluate(self.operation_type, values)
+    }
 }
 
-pub async fn default<T>(_values: &Vec<T>) -> T
-where T: Debug + Default + 'static {
-    Default::default()
+/// Value of every operation, shared by `Operation::process` and
+/// `Operation::evaluate` so that they never disagree
+fn evaluate<T, V>(operation_type: OperationType, values: &[V]) -> T
+where for<'a> T: Operable<'a, T> + 'static, V: Borrow<T> {
+    match operation_type {
+        OperationType::Default | OperationType::Delay => Default::default(),
+        OperationType::Sum => values.iter().map(Borrow::borrow).sum(),
+        OperationType::Product => values.iter().map(Borrow::borrow).product(),
+    }
 }
 
-pub async fn delay<T>(_values: &Vec<T>) -> T
-where T: Debug + Default + 'static {
+pub async fn default<T, V>(values: &[V]) -> T
+where for<'a> T: Operable<'a, T> + 'static, V: Borrow<T> {
+    evaluate(OperationType::Default, values)
+}
+
+pub async fn delay<T, V>(values: &[V]) -> T
+where for<'a> T: Operable<'a, T> + 'static, V: Borrow<T> {
     delay_for(Duration::from_secs(2)).await;
-    Default::default()
+    evaluate(OperationType::Delay, values)
 }
 
-pub async fn sum<T>(values: &Vec<T>) -> T
-where for<'a> T: Debug + Sum<&'a T> + 'static {
-    values.iter().sum()
+pub async fn sum<T, V>(values: &[V]) -> T
+where for<'a> T: Operable<'a, T> + 'static, V: Borrow<T> {
+    evaluate(OperationType::Sum, values)
 }
 
-pub async fn product<T>(values: &Vec<T>) -> T
-where for<'a> T: Debug + Product<&'a T> + 'static {
-    values.iter().product()
+pub async fn product<T, V>(values: &[V]) -> T
+where for<'a> T: Operable<'a, T> + 'static, V: Borrow<T> {
+    evaluate(OperationType::Product, values)
 }
 
 impl Default for Operation {
     fn default() -> Self {
         let operation_type: OperationType = Default::default();
-        Operation { operation_type }
+        Operation::new(operation_type)
     }
 }
 
@@ -71,10 +95,12 @@ impl Default for Operation {
 mod tests {
     use super::*;
 
+    use std::sync::Arc;
+
     #[tokio::test]
     pub async fn product_valid() {
         let operation_type = OperationType::Product;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let values = vec![1, 2, 3];
         let result = operation.process(&values).await;
         assert_eq!(result, 6);
@@ -83,7 +109,7 @@ mod tests {
     #[tokio::test]
     pub async fn delay_valid() {
         let operation_type = OperationType::Delay;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let values = vec![1, 2, 3, 4, 5];
         let result = operation.process(&values).await;
         assert_eq!(result, 0);
@@ -92,16 +118,24 @@ mod tests {
     #[tokio::test]
     pub async fn sum_valid() {
         let operation_type = OperationType::Sum;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let values = vec![1, 2, 3, 4, 5];
         let result = operation.process(&values).await;
         assert_eq!(result, 15);
     }
 
+    #[tokio::test]
+    pub async fn shared_values() {
+        let operation = Operation::new(OperationType::Sum);
+        let values: Vec<Arc<u64>> = vec![Arc::new(1), Arc::new(2), Arc::new(3)];
+        let result: u64 = operation.process(&values).await;
+        assert_eq!(result, 6);
+    }
+
     #[tokio::test]
     pub async fn default_valid() {
         let operation_type = OperationType::Default;
-        let operation = Operation { operation_type };
+        let operation = Operation::new(operation_type);
         let values = vec![1, 123125, 2];
         let result = operation.process(&values).await;
         assert_eq!(result, 0);
@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::dag::NodeId;
+use crate::report::NodeStatus;
+
+/// Sent while a computation runs, as nodes start and finish
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgressEvent {
+    /// The node starts, once its inputs are ready and it has a slot if the
+    /// concurrency is limited and its operation runs.  Nodes taking their
+    /// result from the cache, the debugger or a previous run, or rejected by a
+    /// hook, start too.
+    Started { id: NodeId },
+    /// The node is done, whatever its status, leaving `remaining` of the
+    /// `total` nodes to compute.  Every node that is not pruned finishes once,
+    /// after starting if it completed or failed.  Skipped and cancelled nodes
+    /// may finish without starting.
+    Finished { id: NodeId, status: NodeStatus, remaining: usize, total: usize },
+}
+
+/// Called with every progress event, from the task of the node concerned
+pub type ProgressCallback = Box<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// Counts the finished nodes of a single run
+pub(crate) struct ProgressTracker {
+    callback: ProgressCallback,
+    total: usize,
+    finished: AtomicUsize,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(callback: ProgressCallback, total: usize) -> Self {
+        let finished = AtomicUsize::new(0);
+        Self { callback, total, finished }
+    }
+
+    pub(crate) fn started(&self, id: NodeId) {
+        (self.callback)(&ProgressEvent::Started { id });
+    }
+
+    pub(crate) fn finished(&self, id: NodeId, status: NodeStatus) {
+        let finished = self.finished.fetch_add(1, Ordering::Relaxed) + 1;
+        let remaining = self.total - finished;
+        (self.callback)(&ProgressEvent::Finished { id, status, remaining, total: self.total });
+    }
+}
+
+/// Single line progress bar of the given width, with the rate of nodes per
+/// second so far and the estimated time left at that rate
+pub fn progress_line(finished: usize, total: usize, elapsed: Duration, width: usize) -> String {
+    let ratio = if total == 0 { 1.0 } else { finished as f64 / total as f64 };
+    let filled = ((ratio * width as f64) as usize).min(width);
+    let bar = format!("{}{}", "=".repeat(filled), " ".repeat(width - filled));
+    let seconds = elapsed.as_secs_f64();
+    let rate = if seconds > 0.0 { finished as f64 / seconds } else { 0.0 };
+    let eta = if rate > 0.0 {
+        format!("{}s", ((total - finished) as f64 / rate).ceil() as u64)
+    } else {
+        "?".to_owned()
+    };
+    format!("[{}] {}/{} nodes, {:.1} nodes/s, ETA {}", bar, finished, total, rate, eta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::cache::MemoryCache;
+    use crate::computation::Computation;
+    use crate::dag::Dag;
+    use crate::hook::NodeHook;
+    use crate::operation::{Operation, OperationType};
+
+    #[tokio::test]
+    pub async fn reports_every_node() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Product), vec![id1, id2]);
+        let mut computation = Computation::new(&dag);
+        let mut events = computation.progress_events();
+        computation.process(2u64).await;
+
+        let mut started = vec![];
+        let mut remaining = vec![];
+        while let Ok(event) = events.try_recv() {
+            match event {
+                ProgressEvent::Started { id } => started.push(id),
+                ProgressEvent::Finished { id, status, remaining: left, total } => {
+                    assert_eq!(status, NodeStatus::Completed);
+                    assert_eq!(total, 3);
+                    assert!(started.contains(&id));
+                    remaining.push(left);
+                },
+            }
+        }
+        assert_eq!(started, vec![id1, id2, id3]);
+        assert_eq!(remaining, vec![2, 1, 0]);
+    }
+
+    #[tokio::test]
+    pub async fn callback_counts_cancelled_nodes() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let mut computation = Computation::new(&dag);
+        computation.cancellation_token().cancel();
+        let finished = Arc::new(AtomicUsize::new(0));
+        let counter = finished.clone();
+        computation.set_progress_callback(Box::new(move |event| {
+            if let ProgressEvent::Finished { .. } = event {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }));
+        computation.process(2u64).await;
+        assert_eq!(finished.load(Ordering::Relaxed), 2);
+    }
+
+    /// Rejects the nodes running a product
+    struct RejectProduct;
+
+    impl NodeHook<u64> for RejectProduct {
+        fn before(&self, _id: NodeId, operation: &Operation, _inputs: &mut Vec<Arc<u64>>) -> Result<(), String> {
+            match operation.operation_type {
+                OperationType::Product => Err("no products".to_owned()),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    pub async fn cached_and_rejected_nodes_start() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Product), vec![id1]);
+        let cache = Arc::new(MemoryCache::new(100));
+        let mut computation = Computation::new(&dag);
+        computation.set_cache(cache.clone());
+        computation.process(2u64).await;
+
+        // Every node is a cache hit or rejected on the second run
+        let mut computation = Computation::new(&dag);
+        computation.set_cache(cache);
+        computation.add_hook(RejectProduct);
+        let mut events = computation.progress_events();
+        let report = computation.process(2u64).await;
+        assert_eq!(report.cache.misses, 0);
+
+        let mut started = vec![];
+        let mut finished = vec![];
+        while let Ok(event) = events.try_recv() {
+            match event {
+                ProgressEvent::Started { id } => started.push(id),
+                ProgressEvent::Finished { id, status, .. } => {
+                    assert!(started.contains(&id), "node {} finished without starting", id);
+                    finished.push((id, matches!(status, NodeStatus::Failed(_))));
+                },
+            }
+        }
+        finished.sort();
+        assert_eq!(finished, vec![(id1, false), (id2, false), (id3, true)]);
+    }
+
+    #[test]
+    pub fn progress_line_rate_and_eta() {
+        let line = progress_line(50, 200, Duration::from_secs(10), 10);
+        assert_eq!(line, "[==        ] 50/200 nodes, 5.0 nodes/s, ETA 30s");
+        let line = progress_line(0, 200, Duration::from_secs(0), 4);
+        assert_eq!(line, "[    ] 0/200 nodes, 0.0 nodes/s, ETA ?");
+    }
+}
@@ -17,7 +17,7 @@ impl Distribution<OperationType> for Standard {
 impl Distribution<Operation> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Operation {
         let operation_type: OperationType = rng.gen();
-        Operation { operation_type }
+        Operation::new(operation_type)
     }
 }
 
@@ -55,13 +55,11 @@ impl Distribution<Dag> for DagDistribution {
         let num_nodes: u64 = rng.gen_range(self.min_nodes, self.max_nodes);
         assert!(num_nodes > 0);
         for _ in 0..num_nodes {
-            let parents = dag.nodes.iter().filter_map(|(parent_id, _)| {
-                if rng.gen_ratio(self.edge_percentage, 100) {
-                    Some(parent_id.clone())
-                } else {
-                    None
-                }
-            }).collect();
+            // Go through parents in id order, so that a seeded generator always
+            // creates the same DAG
+            let parents = (1..=dag.current_id)
+                .filter(|_| rng.gen_ratio(self.edge_percentage, 100))
+                .collect();
             let operation = match &self.default_operation {
                 None => rng.gen(),
                 Some(op) => op.clone(),
@@ -77,6 +75,19 @@ impl Distribution<Dag> for DagDistribution {
 mod tests {
     use super::*;
 
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    pub fn seeded_dag() {
+        let distribution = DagDistribution {
+            min_nodes: 10, max_nodes: 20, edge_percentage: 40, default_operation: None,
+        };
+        let dag1 = distribution.sample(&mut StdRng::seed_from_u64(7));
+        let dag2 = distribution.sample(&mut StdRng::seed_from_u64(7));
+        assert_eq!(dag1.fingerprint(), dag2.fingerprint());
+    }
+
     #[test]
     pub fn random_operation_type() {
         let operation_type: OperationType = rand::random();
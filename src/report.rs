@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::cache::CacheStats;
+use crate::dag::NodeId;
+use crate::error::NodeError;
+use crate::lineage::{Lineage, Record};
+use crate::retry::Attempt;
+use crate::watchdog::Stall;
+
+/// Final state of a node once a computation has finished
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeStatus {
+    Completed,
+    Failed(NodeError),
+    /// The node never ran because one of its ancestors did not produce a value
+    Skipped,
+    /// The node was not needed to compute the requested targets
+    Pruned,
+    /// The computation was cancelled, or aborted after another node failed,
+    /// before the node finished
+    Cancelled,
+}
+
+/// Outcome of `Computation::process`, with the values of every sink node, or
+/// every requested target, that completed and the status of every node in the DAG
+#[derive(Debug)]
+pub struct Report<T> {
+    /// Values of the sinks or targets that completed, by node id, so that a
+    /// missing one is never mistaken for another
+    pub results: BTreeMap<NodeId, T>,
+    pub statuses: HashMap<NodeId, NodeStatus>,
+    /// Every attempt made at running each node, empty for skipped nodes
+    pub attempts: HashMap<NodeId, Vec<Attempt>>,
+    /// Whether the computation was cancelled, making this a partial result
+    pub cancelled: bool,
+    /// Why the computation was aborted, if the watchdog found it stalled
+    pub stall: Option<Stall>,
+    /// Cache hits and misses, all zero when no cache was used
+    pub cache: CacheStats,
+    /// Results of the nodes kept with `Computation::retain_values` or
+    /// `Computation::watch`, and of the targets, by node id
+    pub values: HashMap<NodeId, T>,
+    pub(crate) children: HashMap<NodeId, Vec<NodeId>>,
+    /// What every completed node computed, only kept when recording lineage
+    pub(crate) lineage: HashMap<NodeId, Record<T>>,
+}
+
+impl<T> Report<T> {
+    pub fn is_success(&self) -> bool {
+        self.statuses.values().all(|status| matches!(status, NodeStatus::Completed | NodeStatus::Pruned))
+    }
+
+    /// How the value of the node was derived, if it completed and lineage
+    /// was recorded with `Computation::record_lineage`
+    pub fn lineage(&self, id: NodeId) -> Option<Lineage<T>>
+    where T: Clone {
+        let mut parents: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        self.children.iter().for_each(|(id, children)| {
+            children.iter().for_each(|child_id| parents.entry(*child_id).or_default().push(*id));
+        });
+        parents.values_mut().for_each(|parents| parents.sort_unstable());
+        Lineage::build(id, &self.lineage, &parents)
+    }
+
+    pub fn errors(&self) -> Vec<&NodeError> {
+        let mut errors: Vec<&NodeError> = self.statuses.values().filter_map(|status| match status {
+            NodeStatus::Failed(error) => Some(error),
+            _ => None,
+        }).collect();
+        errors.sort_by_key(|error| error.id());
+        errors
+    }
+
+    pub fn completed(&self) -> Vec<NodeId> {
+        self.with_status(&NodeStatus::Completed)
+    }
+
+    pub fn failed(&self) -> Vec<NodeId> {
+        self.errors().iter().map(|error| error.id()).collect()
+    }
+
+    pub fn skipped(&self) -> Vec<NodeId> {
+        self.with_status(&NodeStatus::Skipped)
+    }
+
+    pub fn pruned(&self) -> Vec<NodeId> {
+        self.with_status(&NodeStatus::Pruned)
+    }
+
+    /// Descendants of the given node that were skipped, all of them because
+    /// of that node if it failed
+    pub fn skipped_descendants(&self, id: NodeId) -> Vec<NodeId> {
+        let mut skipped: Vec<NodeId> = self.descendants(id).into_iter()
+            .filter(|id| self.statuses.get(id) == Some(&NodeStatus::Skipped))
+            .collect();
+        skipped.sort_unstable();
+        skipped
+    }
+
+    /// Marks descendants of failed nodes as skipped, even if they were
+    /// cancelled before noticing the failure, since they could never have run
+    pub(crate) fn skip_failed_descendants(&mut self) {
+        for id in self.failed() {
+            for descendant in self.descendants(id) {
+                if let Some(status @ NodeStatus::Cancelled) = self.statuses.get_mut(&descendant) {
+                    *status = NodeStatus::Skipped;
+                }
+            }
+        }
+    }
+
+    fn descendants(&self, id: NodeId) -> HashSet<NodeId> {
+        let mut descendants = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            for child in self.children.get(&id).into_iter().flatten() {
+                if descendants.insert(*child) {
+                    stack.push(*child);
+                }
+            }
+        }
+        descendants
+    }
+
+    fn with_status(&self, expected: &NodeStatus) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self.statuses.iter()
+            .filter(|(_, status)| *status == expected)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::NodeError;
+
+/// How long to wait between two attempts of the same node
+#[derive(Clone, Debug, PartialEq)]
+pub enum Backoff {
+    /// Always wait the same amount of time
+    Fixed(Duration),
+    /// Double the wait after every failed attempt, starting at `initial`,
+    /// never waiting more than `max`
+    Exponential { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// Time to wait after the given failed attempt, counting from 1
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self {
+            Backoff::Fixed(delay) => *delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+                initial.checked_mul(factor).map_or(*max, |delay| delay.min(*max))
+            },
+        }
+    }
+}
+
+/// Predicate deciding whether a failed attempt should be retried
+pub type RetryOn = Arc<dyn Fn(&NodeError) -> bool + Send + Sync>;
+
+/// Describes how many times a failing node is attempted before giving up
+#[derive(Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    /// Only retry errors matching this predicate, or every error if `None`
+    pub retry_on: Option<RetryOn>,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, backoff: Backoff) -> Self {
+        let retry_on = None;
+        Self { max_attempts, backoff, retry_on }
+    }
+
+    /// Returns the delay before the next attempt, or `None` if the node should
+    /// give up after the given failed attempt
+    pub fn next_delay(&self, attempt: u32, error: &NodeError) -> Option<Duration> {
+        let retryable = self.retry_on.as_ref().is_none_or(|retry_on| retry_on(error));
+        if retryable && attempt < self.max_attempts {
+            Some(self.backoff.delay(attempt))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(1, Backoff::Fixed(Duration::from_secs(0)))
+    }
+}
+
+/// Record of a single attempt at running a node's operation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attempt {
+    pub duration: Duration,
+    pub error: Option<NodeError>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::operation::OperationType;
+
+    #[test]
+    pub fn fixed_backoff() {
+        let backoff = Backoff::Fixed(Duration::from_millis(10));
+        assert_eq!(backoff.delay(1), Duration::from_millis(10));
+        assert_eq!(backoff.delay(5), Duration::from_millis(10));
+    }
+
+    #[test]
+    pub fn exponential_backoff() {
+        let backoff = Backoff::Exponential { initial: Duration::from_millis(10), max: Duration::from_millis(50) };
+        assert_eq!(backoff.delay(1), Duration::from_millis(10));
+        assert_eq!(backoff.delay(2), Duration::from_millis(20));
+        assert_eq!(backoff.delay(3), Duration::from_millis(40));
+        assert_eq!(backoff.delay(4), Duration::from_millis(50));
+        assert_eq!(backoff.delay(100), Duration::from_millis(50));
+    }
+
+    #[test]
+    pub fn retry_on_predicate() {
+        let error = NodeError::Panicked { id: 1, operation_type: OperationType::Sum, message: "".to_owned() };
+        let mut policy = RetryPolicy::new(3, Backoff::Fixed(Duration::from_millis(1)));
+        assert_eq!(policy.next_delay(1, &error), Some(Duration::from_millis(1)));
+        assert_eq!(policy.next_delay(3, &error), None);
+        policy.retry_on = Some(Arc::new(|error| matches!(error, NodeError::TimedOut { .. })));
+        assert_eq!(policy.next_delay(1, &error), None);
+    }
+}
@@ -0,0 +1,484 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::oneshot::{Sender, channel};
+use tokio::time::Instant;
+
+use crate::computation::{Computation, Sendable};
+use crate::dag::{Dag, NodeId, topological_order};
+use crate::operation::{Operable, OperationType};
+use crate::report::Report;
+
+/// Estimated running time of every node, used to find the critical path
+#[derive(Clone, Debug)]
+pub struct CostModel {
+    operation_costs: HashMap<OperationType, Duration>,
+    node_costs: HashMap<NodeId, Duration>,
+}
+
+impl CostModel {
+    /// Cost model without any estimate, where every node costs nothing
+    pub fn new() -> Self {
+        let operation_costs = HashMap::new();
+        let node_costs = HashMap::new();
+        Self { operation_costs, node_costs }
+    }
+
+    /// Costs measured in a previous run of the same `Dag`, using the duration
+    /// of the last attempt of every node that ran, and the default static
+    /// estimates for every other node
+    pub fn from_report<T>(report: &Report<T>) -> Self {
+        let mut costs: Self = Default::default();
+        report.attempts.iter().for_each(|(id, attempts)| {
+            if let Some(attempt) = attempts.last() {
+                costs.set_node_cost(*id, attempt.duration);
+            }
+        });
+        costs
+    }
+
+    /// Estimate for every node running the given operation type
+    pub fn set_operation_cost(&mut self, operation_type: OperationType, cost: Duration) {
+        self.operation_costs.insert(operation_type, cost);
+    }
+
+    /// Estimate for a single node, taking precedence over its operation type
+    pub fn set_node_cost(&mut self, id: NodeId, cost: Duration) {
+        self.node_costs.insert(id, cost);
+    }
+
+    pub fn cost(&self, id: NodeId, operation_type: OperationType) -> Duration {
+        self.node_costs.get(&id)
+            .or_else(|| self.operation_costs.get(&operation_type))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl Default for CostModel {
+    /// Static estimates of the provided operations: two seconds for `Delay`,
+    /// and a microsecond for every other operation
+    fn default() -> Self {
+        let mut costs = Self::new();
+        costs.set_operation_cost(OperationType::Default, Duration::from_micros(1));
+        costs.set_operation_cost(OperationType::Delay, Duration::from_secs(2));
+        costs.set_operation_cost(OperationType::Sum, Duration::from_micros(1));
+        costs.set_operation_cost(OperationType::Product, Duration::from_micros(1));
+        costs
+    }
+}
+
+/// Which ready node runs first when the number of running nodes is limited
+#[derive(Clone, Debug, Default)]
+pub enum SchedulingPolicy {
+    /// Nodes run in the order their inputs became ready
+    #[default]
+    Fifo,
+    /// Nodes with the longest estimated path to the end of the computation run
+    /// first
+    CriticalPath(CostModel),
+}
+
+/// Estimated time from the start of every node to the end of the computation,
+/// following its most expensive chain of descendants
+pub fn critical_path(dag: &Dag, costs: &CostModel) -> HashMap<NodeId, Duration> {
+    let operation_types = dag.nodes.iter().map(|(id, node)| (*id, node.operation.operation_type)).collect();
+    let children = dag.nodes.iter().map(|(id, node)| (*id, node.children.clone())).collect();
+    remaining_paths(&children, &operation_types, costs)
+}
+
+pub(crate) fn remaining_paths(
+    children: &HashMap<NodeId, Vec<NodeId>>,
+    operation_types: &HashMap<NodeId, OperationType>,
+    costs: &CostModel,
+) -> HashMap<NodeId, Duration> {
+    let mut paths: HashMap<NodeId, Duration> = HashMap::new();
+    // Visiting in reverse topological order computes every child first, even
+    // when changes to a `Dag` gave a parent a larger id than its children
+    topological_order(children).into_iter().rev()
+        .filter(|id| operation_types.contains_key(id))
+        .for_each(|id| {
+            let longest = children.get(&id).into_iter().flatten()
+                .filter_map(|child_id| paths.get(child_id))
+                .max()
+                .copied()
+                .unwrap_or_default();
+            paths.insert(id, costs.cost(id, operation_types[&id]) + longest);
+        });
+    paths
+}
+
+/// Node waiting for a slot, ordered by priority and then by arrival
+struct Waiter {
+    priority: Duration,
+    sequence: Reverse<u64>,
+    sender: Sender<Permit>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.sequence).cmp(&(other.priority, other.sequence))
+    }
+}
+
+struct SchedulerState {
+    available: usize,
+    waiting: BinaryHeap<Waiter>,
+    sequence: u64,
+    /// Nodes whose inputs are all ready, but which are not yet waiting nor
+    /// finished.  No slot is handed out before they all arrive, so that nodes
+    /// are chosen by priority rather than by which task happened to run first.
+    arriving: usize,
+    /// Nodes counted in `arriving` whose `Arrival` was not taken yet
+    expected: HashSet<NodeId>,
+    /// Number of parents still to complete for every node not yet expected
+    inputs_left: HashMap<NodeId, usize>,
+    children: HashMap<NodeId, Vec<NodeId>>,
+}
+
+/// Limits how many nodes run at once, handing each freed slot to the waiting
+/// node with the highest priority
+#[derive(Clone)]
+pub(crate) struct Scheduler {
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+/// Slot held by a running node, given to the next node when dropped
+pub(crate) struct Permit {
+    scheduler: Option<Scheduler>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            scheduler.release();
+        }
+    }
+}
+
+/// Held by a node whose inputs are ready until it waits for a slot, or
+/// finishes without needing one
+pub(crate) struct Arrival {
+    scheduler: Option<Scheduler>,
+}
+
+impl Drop for Arrival {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.take() {
+            let mut state = scheduler.state.lock().unwrap();
+            state.arriving -= 1;
+            scheduler.dispatch(&mut state);
+        }
+    }
+}
+
+impl Scheduler {
+    /// Scheduler running at most `concurrency` of the nodes with the given
+    /// parents at once, waiting for every start node to arrive before running
+    /// any node
+    pub(crate) fn new(concurrency: NonZeroUsize, parents: &HashMap<NodeId, Vec<NodeId>>) -> Self {
+        let waiting = BinaryHeap::new();
+        let expected: HashSet<NodeId> = parents.iter()
+            .filter(|(_, parents)| parents.is_empty())
+            .map(|(id, _)| *id)
+            .collect();
+        let inputs_left = parents.iter()
+            .filter(|(_, parents)| !parents.is_empty())
+            .map(|(id, parents)| (*id, parents.len()))
+            .collect();
+        let mut children: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        parents.iter().for_each(|(id, parents)| {
+            parents.iter().for_each(|parent_id| children.entry(*parent_id).or_default().push(*id));
+        });
+        let arriving = expected.len();
+        let state = SchedulerState { available: concurrency.get(), waiting, sequence: 0, arriving, expected, inputs_left, children };
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Token that a node must hold until it calls `acquire` or finishes, once
+    /// its inputs are ready.  Only given once, to start nodes and to the nodes
+    /// whose parents all completed.
+    pub(crate) fn arrival(&self, id: NodeId) -> Option<Arrival> {
+        let mut state = self.state.lock().unwrap();
+        state.expected.remove(&id).then(|| Arrival { scheduler: Some(self.clone()) })
+    }
+
+    /// Records that the node completed, before its children receive its
+    /// result, so that the children that are now ready arrive before the slot
+    /// of the node goes to another one
+    pub(crate) fn completed(&self, id: NodeId) {
+        let mut state = self.state.lock().unwrap();
+        // The node may complete without waiting, with a result set in advance
+        state.inputs_left.remove(&id);
+        if state.expected.remove(&id) {
+            state.arriving -= 1;
+        }
+        let children = state.children.get(&id).cloned().unwrap_or_default();
+        children.into_iter().for_each(|child_id| {
+            if let Some(left) = state.inputs_left.get_mut(&child_id) {
+                *left -= 1;
+                if *left == 0 {
+                    state.inputs_left.remove(&child_id);
+                    state.expected.insert(child_id);
+                    state.arriving += 1;
+                }
+            }
+        });
+        self.dispatch(&mut state);
+    }
+
+    /// Waits for a free slot.  Dropping the future before it resolves gives
+    /// up the slot, even if it was already handed over.
+    pub(crate) async fn acquire(&self, priority: Duration, arrival: Option<Arrival>) -> Permit {
+        let receiver = {
+            let mut state = self.state.lock().unwrap();
+            let (sender, receiver) = channel();
+            let sequence = Reverse(state.sequence);
+            state.sequence += 1;
+            state.waiting.push(Waiter { priority, sequence, sender });
+            if let Some(mut arrival) = arrival {
+                arrival.scheduler.take();
+                state.arriving -= 1;
+            }
+            self.dispatch(&mut state);
+            receiver
+        };
+        receiver.await.expect("Scheduler dropped a waiting node")
+    }
+
+    /// Passes the slot of a node straight to the next node of its fused chain,
+    /// taking the arrival of that node, unless another node arriving or
+    /// waiting may come first.  The slot is released otherwise, and the
+    /// arrival given back to wait for a slot like any other node.
+    pub(crate) fn hand_over(&self, permit: Permit, priority: Duration, arrival: Option<Arrival>)
+    -> Result<Permit, Option<Arrival>> {
+        let mut state = self.state.lock().unwrap();
+        let others = state.arriving - usize::from(arrival.is_some());
+        let first = state.waiting.peek().is_none_or(|waiter| waiter.priority < priority);
+        if others > 0 || !first {
+            drop(state);
+            drop(permit);
+            return Err(arrival);
+        }
+        if let Some(mut arrival) = arrival {
+            arrival.scheduler.take();
+            state.arriving -= 1;
+        }
+        Ok(permit)
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.available += 1;
+        self.dispatch(&mut state);
+    }
+
+    /// Hands every free slot to the waiting nodes with the highest priority
+    fn dispatch(&self, state: &mut SchedulerState) {
+        while state.arriving == 0 && state.available > 0 {
+            let waiter = match state.waiting.pop() {
+                Some(waiter) => waiter,
+                None => return,
+            };
+            let permit = Permit { scheduler: Some(self.clone()) };
+            match waiter.sender.send(permit) {
+                Ok(()) => state.available -= 1,
+                // The node stopped waiting, so the slot goes to the next one
+                Err(mut permit) => { permit.scheduler.take(); },
+            }
+        }
+    }
+}
+
+/// Makespans of the same `Dag` under both scheduling policies
+#[derive(Clone, Copy, Debug)]
+pub struct SchedulingComparison {
+    pub fifo: Duration,
+    pub critical_path: Duration,
+}
+
+/// Runs the `Dag` twice with at most `concurrency` nodes running at once,
+/// first in FIFO order and then critical path first, measuring each makespan
+pub async fn compare_scheduling<T>(dag: &Dag, initial: T, concurrency: NonZeroUsize, costs: CostModel) -> SchedulingComparison
+where for<'a> T: Operable<'a, T> + Sendable + 'static {
+    let mut makespans = vec![];
+    for policy in [SchedulingPolicy::Fifo, SchedulingPolicy::CriticalPath(costs)] {
+        let mut computation = Computation::new(dag);
+        computation.set_concurrency(concurrency);
+        computation.set_scheduling_policy(policy);
+        let start = Instant::now();
+        computation.process(initial.clone()).await;
+        makespans.push(start.elapsed());
+    }
+    SchedulingComparison { fifo: makespans[0], critical_path: makespans[1] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fault::{Fault, FaultInjector};
+    use crate::operation::Operation;
+
+    #[test]
+    pub fn critical_path_lengths() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Delay), vec![id1]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let id4 = dag.add_node(Operation::new(OperationType::Delay), vec![id3]);
+        let id5 = dag.add_node(Operation::new(OperationType::Delay), vec![id4]);
+        let mut costs = CostModel::new();
+        costs.set_operation_cost(OperationType::Delay, Duration::from_secs(2));
+        costs.set_node_cost(id2, Duration::from_secs(5));
+        let paths = critical_path(&dag, &costs);
+        assert_eq!(paths[&id5], Duration::from_secs(2));
+        assert_eq!(paths[&id3], Duration::from_secs(4));
+        assert_eq!(paths[&id2], Duration::from_secs(5));
+        assert_eq!(paths[&id1], Duration::from_secs(5));
+    }
+
+    #[test]
+    pub fn critical_path_parent_with_larger_id() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Delay), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Delay), vec![]);
+        dag.add_edge(id2, id1).unwrap();
+        let paths = critical_path(&dag, &Default::default());
+        assert_eq!(paths[&id1], Duration::from_secs(2));
+        assert_eq!(paths[&id2], Duration::from_secs(4));
+    }
+
+    #[tokio::test]
+    pub async fn highest_priority_first() {
+        let scheduler = Scheduler::new(NonZeroUsize::new(1).unwrap(), &HashMap::new());
+        let permit = scheduler.acquire(Duration::from_secs(0), None).await;
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        for priority in [1, 3, 2] {
+            let scheduler = scheduler.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire(Duration::from_secs(priority), None).await;
+                sender.send(priority).unwrap();
+            });
+            // Let the task start waiting before spawning the next one
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        drop(sender);
+        drop(permit);
+        let mut order = vec![];
+        while let Some(priority) = receiver.recv().await {
+            order.push(priority);
+        }
+        assert_eq!(order, vec![3, 2, 1]);
+    }
+
+    #[tokio::test]
+    pub async fn abandoned_slot_released() {
+        let scheduler = Scheduler::new(NonZeroUsize::new(1).unwrap(), &HashMap::new());
+        let permit = scheduler.acquire(Duration::from_secs(0), None).await;
+        let waiting = tokio::time::timeout(Duration::from_millis(10), scheduler.acquire(Duration::from_secs(1), None));
+        assert!(waiting.await.is_err());
+        drop(permit);
+        let acquired = tokio::time::timeout(Duration::from_millis(10), scheduler.acquire(Duration::from_secs(0), None));
+        assert!(acquired.await.is_ok());
+    }
+
+    #[tokio::test]
+    pub async fn waits_for_start_nodes() {
+        let scheduler = Scheduler::new(NonZeroUsize::new(1).unwrap(), &HashMap::from([(1, vec![]), (2, vec![])]));
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        for priority in [1, 2] {
+            let arrival = scheduler.arrival(priority as NodeId).unwrap();
+            let scheduler = scheduler.clone();
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let _permit = scheduler.acquire(Duration::from_secs(priority), Some(arrival)).await;
+                sender.send(priority).unwrap();
+            });
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+        drop(sender);
+        let mut order = vec![];
+        while let Some(priority) = receiver.recv().await {
+            order.push(priority);
+        }
+        assert_eq!(order, vec![2, 1]);
+    }
+
+    #[tokio::test]
+    pub async fn hands_over_unless_another_comes_first() {
+        let scheduler = Scheduler::new(NonZeroUsize::new(1).unwrap(), &HashMap::from([(1, vec![]), (2, vec![1])]));
+        let permit = scheduler.acquire(Duration::from_secs(2), scheduler.arrival(1)).await;
+        scheduler.completed(1);
+        let permit = match scheduler.hand_over(permit, Duration::from_secs(1), scheduler.arrival(2)) {
+            Ok(permit) => permit,
+            Err(_) => panic!("Slot not handed over with no other node waiting"),
+        };
+
+        // A node waiting with a higher priority takes the slot instead
+        let waiting = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire(Duration::from_secs(3), None).await })
+        };
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+        assert!(scheduler.hand_over(permit, Duration::from_secs(1), None).is_err());
+        assert!(tokio::time::timeout(Duration::from_millis(10), waiting).await.is_ok());
+    }
+
+    /// Makespan of the `Dag` with two slots, every node taking `cost`
+    async fn makespan(dag: &Dag, cost: Duration, policy: SchedulingPolicy) -> Duration {
+        let mut computation = Computation::new(dag);
+        computation.set_concurrency(NonZeroUsize::new(2).unwrap());
+        computation.set_scheduling_policy(policy);
+        let mut faults = FaultInjector::new();
+        dag.nodes.keys().for_each(|id| faults.inject(*id, Fault::Delay(cost)));
+        computation.set_fault_injector(faults);
+        let start = Instant::now();
+        computation.process(0u64).await;
+        start.elapsed()
+    }
+
+    #[tokio::test(core_threads = 4)]
+    pub async fn critical_path_makespan() {
+        // A chain of three nodes and three independent nodes, on two slots.
+        // Critical path first runs the chain in one slot and the others
+        // alongside, finishing in three rounds.  In FIFO order, the second
+        // node of the chain waits for every start node to get a slot, which
+        // takes two rounds whatever their order, so the chain ends in round four.
+        let mut dag: Dag = Default::default();
+        let sum = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(sum.clone(), vec![]);
+        let id2 = dag.add_node(sum.clone(), vec![id1]);
+        dag.add_node(sum.clone(), vec![id2]);
+        (0..3).for_each(|_| { dag.add_node(sum.clone(), vec![]); });
+        let cost = Duration::from_millis(100);
+        let mut costs = CostModel::new();
+        costs.set_operation_cost(OperationType::Sum, cost);
+        let comparison = SchedulingComparison {
+            fifo: makespan(&dag, cost, SchedulingPolicy::Fifo).await,
+            critical_path: makespan(&dag, cost, SchedulingPolicy::CriticalPath(costs)).await,
+        };
+        assert!(comparison.fifo >= cost * 4);
+        assert!(comparison.critical_path >= cost * 3);
+        assert!(comparison.critical_path < cost * 4);
+        assert!(comparison.fifo > comparison.critical_path);
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::computation::{Computation, Sendable};
+use crate::dag::{Dag, NodeId};
+use crate::error::ComputationError;
+use crate::operation::{Operable, Operation};
+use crate::report::{NodeStatus, Report};
+
+/// Outcome of `Session::recompute`
+#[derive(Debug)]
+pub struct Recomputation<T> {
+    /// Nodes whose operation ran again, sorted by id
+    pub rerun: Vec<NodeId>,
+    pub report: Report<T>,
+}
+
+/// Stateful evaluation of a `Dag`, remembering the last value of every node so
+/// that changing an input or an operation only recomputes the affected nodes
+pub struct Session<T>
+where for<'a> T: Operable<'a, T> + Sendable + 'static {
+    dag: Dag,
+    initial: T,
+    inputs: HashMap<NodeId, T>,
+    values: HashMap<NodeId, T>,
+    /// Nodes that must run again, along with all of their descendants
+    dirty: HashSet<NodeId>,
+}
+
+impl<T> Session<T>
+where for<'a> T: Operable<'a, T> + Sendable + 'static {
+    /// Creates a session sending `initial` to every start node, with every
+    /// node needing to be computed
+    pub fn new(dag: Dag, initial: T) -> Self {
+        let inputs = HashMap::new();
+        let values = HashMap::new();
+        let dirty = dag.nodes.keys().copied().collect();
+        Self { dag, initial, inputs, values, dirty }
+    }
+
+    pub fn dag(&self) -> &Dag {
+        &self.dag
+    }
+
+    /// Last computed value of the node, if it completed
+    pub fn value(&self, id: NodeId) -> Option<&T> {
+        self.values.get(&id)
+    }
+
+    /// Changes the input of a start node, failing without changing anything
+    /// if the node is unknown or has parents
+    pub fn set_input(&mut self, id: NodeId, input: T) -> Result<(), ComputationError> {
+        if !self.dag.nodes.contains_key(&id) {
+            return Err(ComputationError::UnknownNode(id));
+        }
+        if !self.dag.starts.contains(&id) {
+            return Err(ComputationError::NotStartNode(id));
+        }
+        self.inputs.insert(id, input);
+        self.dirty.insert(id);
+        Ok(())
+    }
+
+    /// Changes the operation of a node, failing if the node is unknown
+    pub fn set_operation(&mut self, id: NodeId, operation: Operation) -> Result<(), ComputationError> {
+        let node = self.dag.nodes.get_mut(&id).ok_or(ComputationError::UnknownNode(id))?;
+        node.operation = operation;
+        self.dirty.insert(id);
+        Ok(())
+    }
+
+    /// Runs every node changed since the last call, along with their
+    /// descendants, reusing the previous values of all other nodes.  Nodes that
+    /// fail or are skipped are run again on the next call.
+    pub async fn recompute(&mut self) -> Recomputation<T> {
+        let mut affected = HashSet::new();
+        std::mem::take(&mut self.dirty).into_iter().for_each(|id| {
+            affected.extend(self.dag.descendants(id).expect("Changed an unknown node"));
+            affected.insert(id);
+        });
+
+        let mut computation = Computation::new(&self.dag);
+        computation.retain_values();
+        // Inputs are only ever set for start nodes of the same `Dag`
+        self.inputs.iter().for_each(|(id, input)| computation.set_input(*id, input.clone()).unwrap());
+        self.dag.nodes.keys().filter(|id| !affected.contains(id)).for_each(|id| {
+            let value = self.values.get(id).expect("Unchanged node has no value");
+            computation.set_result(*id, value.clone()).unwrap();
+        });
+        let mut report = computation.process(self.initial.clone()).await;
+
+        let mut rerun = vec![];
+        affected.into_iter().for_each(|id| {
+            match &report.statuses[&id] {
+                NodeStatus::Completed => {
+                    let value = report.values.remove(&id).expect("Completed node has no value");
+                    self.values.insert(id, value);
+                    rerun.push(id);
+                },
+                status => {
+                    if let NodeStatus::Failed(_) = status {
+                        rerun.push(id);
+                    }
+                    self.values.remove(&id);
+                    self.dirty.insert(id);
+                },
+            }
+        });
+        rerun.sort_unstable();
+        Recomputation { rerun, report }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use crate::operation::OperationType;
+
+    #[tokio::test]
+    pub async fn recompute_changed_input() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![]);
+        let id3 = dag.add_node(operation.clone(), vec![id1]);
+        let id4 = dag.add_node(operation.clone(), vec![id2]);
+        let id5 = dag.add_node(operation.clone(), vec![id3, id4]);
+        let mut session = Session::new(dag, 1);
+
+        let recomputation = session.recompute().await;
+        assert_eq!(recomputation.rerun, vec![id1, id2, id3, id4, id5]);
+        assert_eq!(session.value(id5), Some(&2));
+
+        let recomputation = session.recompute().await;
+        assert!(recomputation.rerun.is_empty());
+        assert_eq!(recomputation.report.results, BTreeMap::from([(id5, 2)]));
+
+        session.set_input(id1, 5).unwrap();
+        let recomputation = session.recompute().await;
+        assert_eq!(recomputation.rerun, vec![id1, id3, id5]);
+        assert_eq!(recomputation.report.results, BTreeMap::from([(id5, 6)]));
+        assert_eq!(session.value(id4), Some(&1));
+        assert_eq!(session.value(id5), Some(&6));
+    }
+
+    #[tokio::test]
+    pub async fn recompute_changed_operation() {
+        let mut dag: Dag = Default::default();
+        let operation = Operation::new(OperationType::Sum);
+        let id1 = dag.add_node(operation.clone(), vec![]);
+        let id2 = dag.add_node(operation.clone(), vec![id1]);
+        let id3 = dag.add_node(operation.clone(), vec![id1, id2]);
+        let id4 = dag.add_node(operation.clone(), vec![id1]);
+        let mut session = Session::new(dag, 3);
+        session.recompute().await;
+        assert_eq!(session.value(id3), Some(&6));
+
+        session.set_operation(id2, Operation::new(OperationType::Default)).unwrap();
+        let recomputation = session.recompute().await;
+        assert_eq!(recomputation.rerun, vec![id2, id3]);
+        assert_eq!(session.value(id3), Some(&3));
+        assert_eq!(session.value(id4), Some(&3));
+    }
+
+    #[tokio::test]
+    pub async fn recompute_after_failure() {
+        let mut dag: Dag = Default::default();
+        let mut operation = Operation::new(OperationType::Delay);
+        operation.timeout = Some(std::time::Duration::from_millis(10));
+        let id1 = dag.add_node(operation, vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let mut session = Session::new(dag, 3);
+        let recomputation = session.recompute().await;
+        assert_eq!(recomputation.rerun, vec![id1]);
+        assert_eq!(session.value(id2), None);
+
+        session.set_operation(id1, Operation::new(OperationType::Sum)).unwrap();
+        let recomputation = session.recompute().await;
+        assert_eq!(recomputation.rerun, vec![id1, id2]);
+        assert_eq!(session.value(id2), Some(&3));
+    }
+
+    #[tokio::test]
+    pub async fn invalid_changes_are_errors() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let mut session = Session::new(dag, 3);
+        session.recompute().await;
+        assert_eq!(session.set_input(id2, 5), Err(ComputationError::NotStartNode(id2)));
+        assert_eq!(session.set_input(7, 5), Err(ComputationError::UnknownNode(7)));
+        assert_eq!(session.set_operation(7, Operation::new(OperationType::Sum)), Err(ComputationError::UnknownNode(7)));
+        let recomputation = session.recompute().await;
+        assert!(recomputation.rerun.is_empty());
+    }
+}
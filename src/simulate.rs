@@ -0,0 +1,179 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use crate::dag::{Dag, NodeId};
+use crate::schedule::{CostModel, SchedulingPolicy, critical_path};
+
+/// When and where a node ran in a simulation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeSchedule {
+    pub worker: usize,
+    pub start: Duration,
+    pub finish: Duration,
+}
+
+/// Outcome of `simulate`, without running any operation
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    pub nodes: HashMap<NodeId, NodeSchedule>,
+    pub workers: usize,
+    /// Time at which the last node finishes
+    pub makespan: Duration,
+    /// Time to run every node one after the other
+    pub sequential: Duration,
+}
+
+impl Simulation {
+    /// Share of the available worker time spent running nodes, between 0 and 1
+    pub fn utilization(&self) -> f64 {
+        if self.makespan.is_zero() {
+            return 0.0;
+        }
+        self.sequential.as_secs_f64() / (self.makespan.as_secs_f64() * self.workers as f64)
+    }
+
+    /// How many times faster than running every node sequentially
+    pub fn speedup(&self) -> f64 {
+        if self.makespan.is_zero() {
+            return 1.0;
+        }
+        self.sequential.as_secs_f64() / self.makespan.as_secs_f64()
+    }
+
+    /// Node ids ordered by start time, then by id
+    pub fn order(&self) -> Vec<NodeId> {
+        let mut ids: Vec<NodeId> = self.nodes.keys().copied().collect();
+        ids.sort_unstable_by_key(|id| (self.nodes[id].start, *id));
+        ids
+    }
+}
+
+/// Simulates running the `Dag` on `workers` workers with list scheduling:
+/// whenever a worker is free, it starts the ready node chosen by the
+/// scheduling policy, which then runs for its cost in `costs`.  A critical path
+/// policy may use different estimates, to simulate inaccurate ones.
+pub fn simulate(dag: &Dag, costs: &CostModel, workers: NonZeroUsize, policy: &SchedulingPolicy) -> Simulation {
+    let workers = workers.get();
+    let priorities = match policy {
+        SchedulingPolicy::Fifo => HashMap::new(),
+        SchedulingPolicy::CriticalPath(costs) => critical_path(dag, costs),
+    };
+    let cost = |id: NodeId| costs.cost(id, dag.nodes[&id].operation.operation_type);
+    let parents = dag.parents();
+    let mut remaining: HashMap<NodeId, usize> = parents.iter().map(|(id, parents)| (*id, parents.len())).collect();
+
+    // Ready nodes by priority, then in the order they became ready
+    let mut ready = BinaryHeap::new();
+    let mut sequence = 0;
+    let mut push_ready = |ready: &mut BinaryHeap<_>, id: NodeId| {
+        let priority = priorities.get(&id).copied().unwrap_or_default();
+        ready.push((priority, Reverse(sequence), id));
+        sequence += 1;
+    };
+    let mut starts = dag.starts.clone();
+    starts.sort_unstable();
+    starts.into_iter().for_each(|id| push_ready(&mut ready, id));
+
+    let mut free: BTreeSet<usize> = (0..workers).collect();
+    // Running nodes by earliest finish time, then by id
+    let mut running: BinaryHeap<Reverse<(Duration, NodeId)>> = BinaryHeap::new();
+    let mut nodes = HashMap::new();
+    let mut now = Duration::default();
+    loop {
+        while !free.is_empty() {
+            let (_, _, id) = match ready.pop() {
+                Some(node) => node,
+                None => break,
+            };
+            let worker = free.pop_first().unwrap();
+            let finish = now + cost(id);
+            nodes.insert(id, NodeSchedule { worker, start: now, finish });
+            running.push(Reverse((finish, id)));
+        }
+
+        let Reverse((finish, _)) = match running.peek() {
+            Some(next) => *next,
+            None => break,
+        };
+        now = finish;
+        // Free every worker finishing now before choosing the next nodes
+        while let Some(Reverse((finish, id))) = running.peek().copied() {
+            if finish > now {
+                break;
+            }
+            running.pop();
+            free.insert(nodes[&id].worker);
+            let mut children = dag.nodes[&id].children.clone();
+            children.sort_unstable();
+            children.into_iter().for_each(|child_id| {
+                let count = remaining.get_mut(&child_id).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    push_ready(&mut ready, child_id);
+                }
+            });
+        }
+    }
+
+    let sequential = dag.nodes.keys().map(|id| cost(*id)).sum();
+    Simulation { nodes, workers, makespan: now, sequential }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::operation::{Operation, OperationType};
+
+    fn secs(seconds: u64) -> Duration {
+        Duration::from_secs(seconds)
+    }
+
+    /// Three independent delays, the last followed by another delay
+    fn chain_dag() -> (Dag, Vec<NodeId>) {
+        let mut dag: Dag = Default::default();
+        let delay = Operation::new(OperationType::Delay);
+        let id1 = dag.add_node(delay.clone(), vec![]);
+        let id2 = dag.add_node(delay.clone(), vec![]);
+        let id3 = dag.add_node(delay.clone(), vec![]);
+        let id4 = dag.add_node(delay.clone(), vec![id3]);
+        (dag, vec![id1, id2, id3, id4])
+    }
+
+    #[test]
+    pub fn fifo_list_scheduling() {
+        let (dag, ids) = chain_dag();
+        let simulation = simulate(&dag, &Default::default(), NonZeroUsize::new(2).unwrap(), &SchedulingPolicy::Fifo);
+        assert_eq!(simulation.nodes[&ids[0]], NodeSchedule { worker: 0, start: secs(0), finish: secs(2) });
+        assert_eq!(simulation.nodes[&ids[1]], NodeSchedule { worker: 1, start: secs(0), finish: secs(2) });
+        assert_eq!(simulation.nodes[&ids[2]], NodeSchedule { worker: 0, start: secs(2), finish: secs(4) });
+        assert_eq!(simulation.nodes[&ids[3]], NodeSchedule { worker: 0, start: secs(4), finish: secs(6) });
+        assert_eq!(simulation.makespan, secs(6));
+        assert_eq!(simulation.sequential, secs(8));
+        assert!((simulation.speedup() - 8.0 / 6.0).abs() < 1e-9);
+        assert!((simulation.utilization() - 8.0 / 12.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn critical_path_list_scheduling() {
+        let (dag, ids) = chain_dag();
+        let policy = SchedulingPolicy::CriticalPath(Default::default());
+        let simulation = simulate(&dag, &Default::default(), NonZeroUsize::new(2).unwrap(), &policy);
+        assert_eq!(simulation.order(), vec![ids[0], ids[2], ids[1], ids[3]]);
+        assert_eq!(simulation.makespan, secs(4));
+        assert!((simulation.speedup() - 2.0).abs() < 1e-9);
+        assert!((simulation.utilization() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    pub fn single_worker_is_sequential() {
+        let (dag, _) = chain_dag();
+        let mut costs = CostModel::new();
+        costs.set_operation_cost(OperationType::Delay, secs(3));
+        let simulation = simulate(&dag, &costs, NonZeroUsize::new(1).unwrap(), &SchedulingPolicy::Fifo);
+        assert_eq!(simulation.makespan, simulation.sequential);
+        assert_eq!(simulation.makespan, secs(12));
+    }
+}
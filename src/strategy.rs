@@ -0,0 +1,134 @@
+use std::num::Wrapping;
+
+use proptest::arbitrary::Arbitrary;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::strategy::BoxedStrategy;
+
+use crate::dag::{Dag, NodeId};
+use crate::operation::{Operation, OperationType};
+
+impl Arbitrary for OperationType {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Every operation but `Delay`, like the `Standard` distribution, so that
+    /// generated DAGs run instantly.  Shrinks towards `Default`.
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(OperationType::Default),
+            Just(OperationType::Sum),
+            Just(OperationType::Product),
+        ].boxed()
+    }
+}
+
+impl Arbitrary for Operation {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        any::<OperationType>().prop_map(Operation::new).boxed()
+    }
+}
+
+/// Bounds of the generated DAGs
+#[derive(Clone, Copy, Debug)]
+pub struct DagParameters {
+    pub max_nodes: usize,
+}
+
+impl Default for DagParameters {
+    fn default() -> Self {
+        DagParameters { max_nodes: 20 }
+    }
+}
+
+/// Node of a generated DAG, with a flag for every earlier node telling
+/// whether it is a parent.  Extra flags are ignored.
+type NodeSpec = (Operation, Vec<bool>);
+
+impl Arbitrary for Dag {
+    type Parameters = DagParameters;
+    type Strategy = BoxedStrategy<Self>;
+
+    /// Non-empty DAGs where every node only takes parents among the nodes
+    /// created before it, so the graph stays acyclic however it shrinks.
+    /// Shrinking removes nodes and edges, and simplifies operations.
+    fn arbitrary_with(parameters: DagParameters) -> Self::Strategy {
+        let max_nodes = parameters.max_nodes;
+        assert!(max_nodes > 0);
+        let node = (any::<Operation>(), vec(any::<bool>(), 0..max_nodes));
+        vec(node, 1..=max_nodes).prop_map(build_dag).boxed()
+    }
+}
+
+fn build_dag(nodes: Vec<NodeSpec>) -> Dag {
+    let mut dag: Dag = Default::default();
+    let mut ids: Vec<NodeId> = vec![];
+    nodes.into_iter().for_each(|(operation, parents)| {
+        let parents = ids.iter().zip(parents)
+            .filter(|(_, is_parent)| *is_parent)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.push(dag.add_node(operation, parents));
+    });
+    dag
+}
+
+/// Small input values, with wrapping arithmetic so that products overflow
+/// the same way everywhere
+pub fn inputs() -> impl Strategy<Value = Wrapping<u64>> {
+    (0u64..10).prop_map(Wrapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::test_runner::{Config, RngAlgorithm, TestError, TestRng, TestRunner};
+    use tokio::runtime::Runtime;
+
+    use crate::computation::Computation;
+
+    proptest! {
+        #[test]
+        fn generated_dags_are_acyclic(dag in any::<Dag>()) {
+            let parents = dag.parents();
+            for (id, node) in &dag.nodes {
+                prop_assert!(node.children.iter().all(|child_id| child_id > id));
+                prop_assert_eq!(dag.starts.contains(id), parents[id].is_empty());
+            }
+        }
+
+        #[test]
+        fn computation_matches_sequential(dag in any::<Dag>(), initial in inputs()) {
+            let expected = dag.evaluate_sequential(initial);
+            let mut computation = Computation::new(&dag);
+            computation.retain_values();
+            let report = Runtime::new().unwrap().block_on(computation.process(initial));
+            prop_assert!(report.is_success());
+            prop_assert_eq!(report.values, expected);
+        }
+    }
+
+    #[test]
+    pub fn shrinks_to_minimal_dag() {
+        // Fails on any node with two parents, which needs three nodes and two edges
+        let config = Config { failure_persistence: None, ..Config::default() };
+        let mut runner = TestRunner::new_with_rng(config, TestRng::deterministic_rng(RngAlgorithm::ChaCha));
+        let result = runner.run(&any::<Dag>(), |dag| {
+            let parents = dag.parents();
+            prop_assert!(parents.values().all(|parents| parents.len() < 2));
+            Ok(())
+        });
+        match result {
+            Err(TestError::Fail(_, dag)) => {
+                assert_eq!(dag.nodes.len(), 3);
+                let edges: usize = dag.nodes.values().map(|node| node.children.len()).sum();
+                assert_eq!(edges, 2);
+            },
+            _ => panic!("No counterexample found"),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::pending;
+use tokio::time::{Instant, delay_until};
+
+use crate::dag::NodeId;
+
+/// State of a computation in which no node completed for too long, which was
+/// then aborted
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stall {
+    /// Time without any node completing
+    pub idle: Duration,
+    /// Nodes that never started, with the parents they were still waiting
+    /// on.  A node waiting on no parent was waiting for its initial value or
+    /// for a slot to run in.
+    pub waiting: BTreeMap<NodeId, Vec<NodeId>>,
+    /// Nodes whose operation was still running
+    pub running: Vec<NodeId>,
+}
+
+impl fmt::Display for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no node completed for {:?}", self.idle)?;
+        for id in &self.running {
+            write!(f, "\n  node {} running", id)?;
+        }
+        for (id, parents) in &self.waiting {
+            if parents.is_empty() {
+                write!(f, "\n  node {} waiting to start", id)?;
+            } else {
+                write!(f, "\n  node {} waiting on {:?}", id, parents)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for Stall {}
+
+struct WatchState {
+    /// When a node last finished, when the run started, or when the last
+    /// pause ended
+    last: Instant,
+    /// Number of nodes held by the debugger, during which nothing stalls
+    paused: usize,
+    running: HashSet<NodeId>,
+    finished: HashSet<NodeId>,
+}
+
+/// Follows the nodes of a single run, to notice when none of them completes
+/// for longer than the idle period
+pub(crate) struct Watchdog {
+    idle: Duration,
+    parents: HashMap<NodeId, Vec<NodeId>>,
+    state: Mutex<WatchState>,
+}
+
+impl Watchdog {
+    /// Watches the nodes with the given parents, all of them in the run
+    pub(crate) fn new(idle: Duration, parents: HashMap<NodeId, Vec<NodeId>>) -> Self {
+        let last = Instant::now();
+        let running = HashSet::new();
+        let finished = HashSet::new();
+        Self { idle, parents, state: Mutex::new(WatchState { last, paused: 0, running, finished }) }
+    }
+
+    /// Suspends the watchdog until the returned guard is dropped, the idle
+    /// period then starting over
+    pub(crate) fn pause(&self) -> WatchPause<'_> {
+        self.state.lock().unwrap().paused += 1;
+        WatchPause(self)
+    }
+
+    pub(crate) fn started(&self, id: NodeId) {
+        self.state.lock().unwrap().running.insert(id);
+    }
+
+    pub(crate) fn finished(&self, id: NodeId) {
+        let mut state = self.state.lock().unwrap();
+        state.running.remove(&id);
+        state.finished.insert(id);
+        state.last = Instant::now();
+    }
+
+    /// Resolves once no node has finished for the idle period, never if every
+    /// node finishes first
+    pub(crate) async fn stalled(&self) -> Stall {
+        loop {
+            let deadline = {
+                let state = self.state.lock().unwrap();
+                if state.finished.len() == self.parents.len() {
+                    break;
+                }
+                if state.paused > 0 {
+                    Instant::now() + self.idle
+                } else if state.last.elapsed() >= self.idle {
+                    return self.stall(&state);
+                } else {
+                    state.last + self.idle
+                }
+            };
+            delay_until(deadline).await;
+        }
+        pending().await
+    }
+
+    fn stall(&self, state: &WatchState) -> Stall {
+        let mut running: Vec<NodeId> = state.running.iter().copied().collect();
+        running.sort_unstable();
+        let waiting = self.parents.iter()
+            .filter(|(id, _)| !state.finished.contains(id) && !state.running.contains(id))
+            .map(|(id, parents)| {
+                let mut parents: Vec<NodeId> = parents.iter()
+                    .filter(|parent_id| !state.finished.contains(parent_id))
+                    .copied()
+                    .collect();
+                parents.sort_unstable();
+                (*id, parents)
+            })
+            .collect();
+        Stall { idle: self.idle, waiting, running }
+    }
+}
+
+/// Keeps the watchdog suspended while alive
+pub(crate) struct WatchPause<'a>(&'a Watchdog);
+
+impl Drop for WatchPause<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.paused -= 1;
+        state.last = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use tokio::time::delay_for;
+
+    use crate::computation::Computation;
+    use crate::dag::Dag;
+    use crate::debugger::Debugger;
+    use crate::fault::{Fault, FaultInjector};
+    use crate::operation::{Operation, OperationType};
+    use crate::report::NodeStatus;
+
+    #[tokio::test]
+    pub async fn aborts_hung_computation() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id1, id2]);
+        let id4 = dag.add_node(Operation::new(OperationType::Sum), vec![id3]);
+        let mut injector = FaultInjector::new();
+        injector.inject(id1, Fault::Hang);
+        let mut computation = Computation::new(&dag);
+        computation.set_fault_injector(injector);
+        computation.set_watchdog(Duration::from_millis(100));
+        let report = computation.process(1u64).await;
+
+        let stall = report.stall.expect("No stall detected");
+        assert_eq!(stall.running, vec![id1]);
+        let waiting: BTreeMap<NodeId, Vec<NodeId>> = vec![(id3, vec![id1]), (id4, vec![id3])].into_iter().collect();
+        assert_eq!(stall.waiting, waiting);
+        assert_eq!(report.statuses[&id1], NodeStatus::Cancelled);
+        assert_eq!(report.statuses[&id2], NodeStatus::Completed);
+        assert!(!report.cancelled);
+        assert_eq!(stall.to_string(), format!(
+            "no node completed for 100ms\n  node {} running\n  node {} waiting on [{}]\n  node {} waiting on [{}]",
+            id1, id3, id1, id4, id3,
+        ));
+    }
+
+    #[tokio::test]
+    pub async fn quiet_when_nodes_complete() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let mut injector = FaultInjector::new();
+        injector.inject(id1, Fault::Delay(Duration::from_millis(50)));
+        injector.inject(id2, Fault::Delay(Duration::from_millis(50)));
+        let mut computation = Computation::new(&dag);
+        computation.set_fault_injector(injector);
+        computation.set_watchdog(Duration::from_millis(80));
+        let report = computation.process(1u64).await;
+        assert!(report.is_success());
+        assert_eq!(report.stall, None);
+    }
+
+    #[tokio::test]
+    pub async fn suspended_while_debugger_paused() {
+        let mut dag: Dag = Default::default();
+        let id1 = dag.add_node(Operation::new(OperationType::Sum), vec![]);
+        let id2 = dag.add_node(Operation::new(OperationType::Sum), vec![id1]);
+        let debugger = Arc::new(Debugger::new(false));
+        debugger.break_at(id2);
+        let mut computation = Computation::new(&dag);
+        let mut pauses = computation.set_debugger(debugger);
+        computation.set_watchdog(Duration::from_millis(50));
+        let run = tokio::spawn(computation.process(1u64));
+
+        let pause = pauses.recv().await.unwrap();
+        delay_for(Duration::from_millis(200)).await;
+        pause.resume();
+        let report = run.await.unwrap();
+        assert!(report.is_success());
+        assert_eq!(report.stall, None);
+    }
+}
@@ -0,0 +1,98 @@
+//! Runs computations on worker processes started from the command-line binary,
+//! as they would be deployed.
+#![cfg(feature = "serialize")]
+
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use tokio::time::delay_for;
+
+use computation_dag_async::computation::Computation;
+use computation_dag_async::dag::Dag;
+use computation_dag_async::distributed::{Address, Coordinator};
+use computation_dag_async::error::NodeError;
+use computation_dag_async::operation::{Operation, OperationType};
+
+/// Worker process listening on a Unix socket, killed when dropped
+struct Worker {
+    process: Child,
+    path: PathBuf,
+}
+
+impl Worker {
+    async fn start(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("dag-{}-{}.sock", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let process = Command::new(env!("CARGO_BIN_EXE_computation-dag-async"))
+            .args(["--mode", "worker", "--listen", &format!("unix:{}", path.display())])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Error starting worker process");
+        while !path.exists() {
+            delay_for(Duration::from_millis(10)).await;
+        }
+        Self { process, path }
+    }
+
+    fn address(&self) -> Address {
+        Address::Unix(self.path.clone())
+    }
+
+    fn kill(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.kill();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[tokio::test(core_threads = 4)]
+pub async fn matches_local_computation() {
+    let mut dag: Dag = Default::default();
+    let sum = Operation::new(OperationType::Sum);
+    let product = Operation::new(OperationType::Product);
+    let id1 = dag.add_node(sum.clone(), vec![]);
+    let id2 = dag.add_node(sum.clone(), vec![]);
+    let id3 = dag.add_node(product.clone(), vec![id1, id2]);
+    let id4 = dag.add_node(sum.clone(), vec![id1, id3]);
+    let id5 = dag.add_node(product, vec![id3, id4]);
+    dag.add_node(sum.clone(), vec![id5, id2]);
+    dag.add_node(sum, vec![id4]);
+    let workers = [Worker::start("local-1").await, Worker::start("local-2").await];
+    let addresses = workers.iter().map(Worker::address).collect();
+    let report = Coordinator::new(&dag, addresses).process(3u128).await.unwrap();
+    let local = Computation::new(&dag).process(3u128).await;
+    assert!(report.is_success());
+    assert_eq!(report.results, local.results);
+}
+
+#[tokio::test(core_threads = 4)]
+pub async fn survives_killed_worker() {
+    let mut dag: Dag = Default::default();
+    let delay = Operation::new(OperationType::Delay);
+    let id1 = dag.add_node(delay.clone(), vec![]);
+    let id2 = dag.add_node(delay, vec![]);
+    let id3 = dag.add_node(Operation::new(OperationType::Sum), vec![id1, id2]);
+    let mut workers = [Worker::start("killed").await, Worker::start("surviving").await];
+    let addresses = workers.iter().map(Worker::address).collect();
+    let processing = tokio::spawn(async move {
+        let dag = dag;
+        Coordinator::new(&dag, addresses).process(3u128).await
+    });
+    // Both delays are running, one on each worker
+    delay_for(Duration::from_millis(500)).await;
+    workers[0].kill();
+    let report = processing.await.unwrap().unwrap();
+    assert!(report.is_success());
+    assert_eq!(report.results[&id3], 0);
+    let lost = report.attempts.values().flatten()
+        .filter(|attempt| matches!(attempt.error, Some(NodeError::WorkerLost { .. })))
+        .count();
+    assert_eq!(lost, 1);
+}